@@ -0,0 +1,63 @@
+#[macro_use]
+extern crate criterion;
+extern crate thread_control;
+
+use std::sync::{Arc, Barrier};
+use std::thread;
+use std::time::{Duration, Instant};
+use criterion::{black_box, Criterion};
+use thread_control::*;
+
+const WORKERS: usize = 8;
+const CHECKS: usize = 100_000;
+
+fn alive(c: &mut Criterion) {
+    let (flag, _control) = make_pair();
+    c.bench_function("alive", |b| b.iter(|| black_box(&flag).alive()));
+    c.bench_function("is_alive", |b| b.iter(|| black_box(&flag).is_alive()));
+}
+
+// Workers check clones of the same flag at once
+fn contended<F>(iters: u64, check: F) -> Duration
+    where F: Fn(Flag) + Send + Sync + 'static
+{
+    let check = Arc::new(check);
+    let mut total = Duration::default();
+    for _ in 0..iters {
+        let (flag, _control) = make_pair();
+        let barrier = Arc::new(Barrier::new(WORKERS + 1));
+        let handles: Vec<_> = (0..WORKERS).map(|_| {
+            let flag = flag.clone_independent();
+            let barrier = barrier.clone();
+            let check = check.clone();
+            thread::spawn(move || {
+                barrier.wait();
+                check(flag);
+            })
+        }).collect();
+        barrier.wait();
+        let started = Instant::now();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        total += started.elapsed();
+    }
+    total
+}
+
+fn alive_contended(c: &mut Criterion) {
+    c.bench_function("alive_contended", |b| b.iter_custom(|iters| contended(iters, |flag| {
+        for _ in 0..CHECKS {
+            black_box(flag.alive());
+        }
+    })));
+    c.bench_function("cached_alive_contended", |b| b.iter_custom(|iters| contended(iters, |flag| {
+        let flag = flag.cached(64);
+        for _ in 0..CHECKS {
+            black_box(flag.alive());
+        }
+    })));
+}
+
+criterion_group!(benches, alive, alive_contended);
+criterion_main!(benches);
@@ -0,0 +1,77 @@
+#[macro_use]
+extern crate criterion;
+extern crate thread_control;
+
+use criterion::{black_box, Criterion};
+use thread_control::*;
+
+#[derive(Clone)]
+struct Large([u64; 1024]);
+
+fn round_trip(c: &mut Criterion) {
+    let large = Large([7; 1024]);
+
+    let (requester, responder) = interaction::<Large, Large>();
+    c.bench_function("round_trip_unboxed", |b| b.iter(|| {
+        requester.submit(black_box(large.clone())).unwrap();
+        let (id, req) = responder.get_request().unwrap();
+        responder.set_response(id, req);
+        requester.wait(id, None).unwrap().0[0]
+    }));
+
+    let (requester, responder) = interaction_boxed::<Large, Large>();
+    c.bench_function("round_trip_boxed", |b| b.iter(|| {
+        requester.submit(Box::new(black_box(large.clone()))).unwrap();
+        let (id, req) = responder.get_request().unwrap();
+        responder.set_response(id, req);
+        requester.wait(id, None).unwrap().0[0]
+    }));
+}
+
+fn fast_responder(c: &mut Criterion) {
+    let (requester, responder) = interaction::<u64, u64>();
+    let control = responder.spawn_loop(|req| req + 1);
+
+    let yielding = requester.clone().with_spins(0);
+    c.bench_function("request_pure_yield", |b| b.iter(|| {
+        yielding.request(black_box(1), None).unwrap()
+    }));
+
+    c.bench_function("request_spin_then_yield", |b| b.iter(|| {
+        requester.request(black_box(1), None).unwrap()
+    }));
+
+    // Locks again to wait, unlike `request()`
+    c.bench_function("submit_then_wait", |b| b.iter(|| {
+        let id = yielding.submit(black_box(1)).unwrap();
+        yielding.wait(id, None).unwrap()
+    }));
+
+    control.stop();
+}
+
+fn pinned(c: &mut Criterion) {
+    let (requester, responder) = interaction::<u64, u64>();
+    let control = responder.spawn_loop(|req| req + 1);
+    let mut group = c.benchmark_group("10k_requests");
+    group.sample_size(10);
+
+    group.bench_function("upgrade_per_call", |b| b.iter(|| {
+        for req in 0..10_000 {
+            requester.request(black_box(req), None).unwrap();
+        }
+    }));
+
+    group.bench_function("pinned", |b| b.iter(|| {
+        let pinned = requester.pin().unwrap();
+        for req in 0..10_000 {
+            pinned.request(black_box(req), None).unwrap();
+        }
+    }));
+
+    group.finish();
+    control.stop();
+}
+
+criterion_group!(benches, round_trip, fast_responder, pinned);
+criterion_main!(benches);
@@ -0,0 +1,15 @@
+#[macro_use]
+extern crate criterion;
+extern crate thread_control;
+
+use criterion::{black_box, Criterion};
+use thread_control::*;
+
+fn pool(c: &mut Criterion) {
+    c.bench_function("make_pair fresh", |b| b.iter(|| black_box(make_pair())));
+    let pool = FlagPool::new();
+    c.bench_function("make_pair pooled", |b| b.iter(|| black_box(pool.make_pair())));
+}
+
+criterion_group!(benches, pool);
+criterion_main!(benches);
@@ -0,0 +1,114 @@
+//! Cancellation of async tasks by the state of threads.
+//!
+//! Example:
+//!
+//! ```rust,edition2021
+//! use std::thread;
+//! use std::time::Duration;
+//! use thread_control::*;
+//!
+//! #[tokio::main(flavor = "current_thread")]
+//! async fn main() {
+//!     let (flag, control) = make_pair();
+//!     let handle = thread::spawn(move || {
+//!         while flag.alive() {
+//!             thread::sleep(Duration::from_millis(1));
+//!         }
+//!     });
+//!     let stopper = control.clone();
+//!     thread::spawn(move || {
+//!         thread::sleep(Duration::from_millis(50));
+//!         stopper.stop();
+//!     });
+//!     let cancelled = tokio::select! {
+//!         _ = control.cancelled() => true,
+//!         _ = tokio::time::sleep(Duration::from_secs(10)) => false,
+//!     };
+//!     assert_eq!(cancelled, true);
+//!     handle.join().unwrap();
+//! }
+//! ```
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::Ordering;
+use std::task::{Context, Poll};
+use tokio::sync::futures::OwnedNotified;
+use tokio::sync::watch;
+use {Control, Status};
+
+/// Future which resolves when the thread is stopped,
+/// interrupted or done, made by `Control::cancelled()`.
+#[derive(Debug)]
+pub struct Cancelled {
+    control: Control,
+    notified: Pin<Box<OwnedNotified>>,
+}
+
+impl Future for Cancelled {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        loop {
+            // Registered before the check to not miss a notification in between
+            let notified = self.notified.as_mut().poll(cx);
+            if self.control.is_cancelled() {
+                return Poll::Ready(());
+            }
+            match notified {
+                Poll::Ready(()) => {
+                    self.notified = Box::pin(self.control.shared.cancel.clone().notified_owned());
+                },
+                Poll::Pending => {
+                    return Poll::Pending;
+                },
+            }
+        }
+    }
+}
+
+impl Control {
+    /// Returns future which resolves when the thread is stopped,
+    /// interrupted or done. Use it in `tokio::select!` to react
+    /// to `stop()` of a thread in async tasks.
+    pub fn cancelled(&self) -> Cancelled {
+        Cancelled {
+            control: self.clone(),
+            notified: Box::pin(self.shared.cancel.clone().notified_owned()),
+        }
+    }
+
+    /// Returns receiver of the latest status of the thread.
+    /// It's updated when the flag is dropped, so async tasks can
+    /// `changed().await` to learn how the thread ended.
+    ///
+    /// Example:
+    ///
+    /// ```rust,edition2021
+    /// use std::thread;
+    /// use std::time::Duration;
+    /// use thread_control::*;
+    ///
+    /// #[tokio::main(flavor = "current_thread")]
+    /// async fn main() {
+    ///     let (flag, control) = make_pair();
+    ///     let mut status = control.status_watch();
+    ///     assert_eq!(*status.borrow(), Status::Running);
+    ///     thread::spawn(move || {
+    ///         while flag.alive() {
+    ///             thread::sleep(Duration::from_millis(1));
+    ///         }
+    ///     });
+    ///     control.stop();
+    ///     status.changed().await.unwrap();
+    ///     assert_eq!(*status.borrow(), Status::Stopped);
+    /// }
+    /// ```
+    pub fn status_watch(&self) -> watch::Receiver<Status> {
+        self.shared.status_watch.lock().expect("status watch poisoned").subscribe()
+    }
+
+    fn is_cancelled(&self) -> bool {
+        !self.shared.alive.load(Ordering::Relaxed) || self.is_interrupted() || self.is_done()
+    }
+}
@@ -0,0 +1,55 @@
+//! Counters exported with the `metrics` feature.
+//!
+//! The feature increments these counters of the `metrics` crate:
+//!
+//! * `thread_control.stops` - calls of `Control::stop()`
+//! * `thread_control.interrupts` - interrupts requested by controls or flags
+//! * `thread_control.panics` - flags dropped while panicking
+//! * `thread_control.requests` - requests submitted to interactions
+//!
+//! Counters of pairs named by `ControlBuilder::name()` carry the name
+//! in the `name` label.
+//!
+//! Without the feature the calls compile to nothing.
+
+#[cfg(feature = "metrics")]
+macro_rules! increment {
+    ($name:expr) => {
+        counter!($name).increment(1)
+    };
+    ($name:expr, $pair:expr) => {
+        match $pair {
+            Some(pair) => counter!($name, "name" => pair.to_owned()).increment(1),
+            None => counter!($name).increment(1),
+        }
+    };
+}
+
+#[cfg(not(feature = "metrics"))]
+macro_rules! increment {
+    ($name:expr) => {
+    };
+    ($name:expr, $pair:expr) => {
+        let _ = $pair;
+    };
+}
+
+#[inline]
+pub(crate) fn stop(pair: Option<&str>) {
+    increment!("thread_control.stops", pair);
+}
+
+#[inline]
+pub(crate) fn interrupt(pair: Option<&str>) {
+    increment!("thread_control.interrupts", pair);
+}
+
+#[inline]
+pub(crate) fn panic(pair: Option<&str>) {
+    increment!("thread_control.panics", pair);
+}
+
+#[inline]
+pub(crate) fn request() {
+    increment!("thread_control.requests");
+}
@@ -0,0 +1,83 @@
+//! Wakeups of event loops blocked in `poll()` or `epoll_wait()`.
+//!
+//! A worker which waits for events doesn't check its flag until
+//! an event comes. `Flag::stop_eventfd()` returns an `eventfd` which
+//! becomes readable on `stop()`, `interrupt()` and other calls which
+//! wake the worker, so the worker adds it to its poll set and checks
+//! the flag when it fires.
+//!
+//! Lifetime of the descriptor:
+//!
+//! * It's created by the first call of `stop_eventfd()`, the next
+//!   calls return the same descriptor.
+//! * It's owned by the pair and closed when the flag and all its
+//!   controls are dropped, so it's valid while the flag exists.
+//!   Don't close it yourself.
+//! * It stays readable after the first wakeup, reading it
+//!   resets the counter.
+//!
+//! Example:
+//!
+//! ```rust
+//! extern crate libc;
+//! extern crate thread_control;
+//!
+//! use std::thread;
+//! use thread_control::*;
+//!
+//! fn main() {
+//!     let (flag, control) = make_pair();
+//!     let fd = flag.stop_eventfd();
+//!     let handle = thread::spawn(move || {
+//!         while flag.alive() {
+//!             let mut fds = [libc::pollfd { fd, events: libc::POLLIN, revents: 0 }];
+//!             // Blocks forever, only the stop fires
+//!             unsafe { libc::poll(fds.as_mut_ptr(), 1, -1) };
+//!         }
+//!     });
+//!     control.stop();
+//!     handle.join().unwrap();
+//!     assert_eq!(control.status(), Status::Stopped);
+//! }
+//! ```
+
+use std::fs::File;
+use std::io::{self, Write};
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::sync::Mutex;
+use libc;
+use Flag;
+
+// Makes the descriptor readable, it's non-blocking, so a full counter is ignored
+pub(crate) fn wake(eventfd: &Mutex<Option<File>>) {
+    if let Some(ref file) = *eventfd.lock().expect("eventfd poisoned") {
+        let _ = (&*file).write(&1u64.to_ne_bytes());
+    }
+}
+
+impl Flag {
+    /// Return `eventfd` which becomes readable when the worker
+    /// should check the flag. See the module docs for its lifetime.
+    ///
+    /// # Panics
+    ///
+    /// This method panics, if the descriptor can't be created.
+    pub fn stop_eventfd(&self) -> RawFd {
+        let mut eventfd = self.shared.eventfd.lock().expect("eventfd poisoned");
+        if let Some(ref file) = *eventfd {
+            return file.as_raw_fd();
+        }
+        let fd = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC | libc::EFD_NONBLOCK) };
+        if fd < 0 {
+            panic!("can't create eventfd: {}", io::Error::last_os_error());
+        }
+        // Owned by the file from now on and closed with it
+        let file = unsafe { File::from_raw_fd(fd) };
+        // Checked under the lock, wakeups which came before it saw no descriptor
+        if !self.is_alive() {
+            let _ = (&file).write(&1u64.to_ne_bytes());
+        }
+        *eventfd = Some(file);
+        fd
+    }
+}
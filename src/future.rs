@@ -0,0 +1,135 @@
+//! Futures which complete on state changes of threads.
+//!
+//! Futures don't poll `Control` in a loop, they are woken
+//! by the flag when it's dropped or by the control when
+//! it stops or interrupts the thread.
+//!
+//! Example:
+//!
+//! ```rust
+//! extern crate futures;
+//! extern crate thread_control;
+//!
+//! use std::future::Future;
+//! use std::pin::Pin;
+//! use std::task::{Context, Poll};
+//! use std::thread;
+//! use std::time::Duration;
+//! use thread_control::*;
+//!
+//! struct CountPolls<F> {
+//!     future: F,
+//!     polls: usize,
+//! }
+//!
+//! impl<F: Future + Unpin> Future for CountPolls<F> {
+//!     type Output = (F::Output, usize);
+//!
+//!     fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+//!         self.polls += 1;
+//!         let polls = self.polls;
+//!         Pin::new(&mut self.future).poll(cx).map(|output| (output, polls))
+//!     }
+//! }
+//!
+//! fn main() {
+//!     let (flag, control) = make_pair();
+//!     let handle = thread::spawn(move || {
+//!         while flag.alive() {
+//!             thread::sleep(Duration::from_millis(1));
+//!         }
+//!     });
+//!     let stopper = control.clone();
+//!     thread::spawn(move || {
+//!         thread::sleep(Duration::from_millis(50));
+//!         stopper.stop();
+//!     });
+//!     let future = CountPolls { future: control.done(), polls: 0 };
+//!     let (status, polls) = futures::executor::block_on(future);
+//!     assert_eq!(status, Status::Stopped);
+//!     // The first poll, a wake up by `stop()` and one by the drop of the flag
+//!     assert!(polls <= 3);
+//!     handle.join().unwrap();
+//! }
+//! ```
+//!
+//! Flags and controls stay usable with `catch_unwind` with the feature:
+//!
+//! ```rust
+//! use std::panic::{RefUnwindSafe, UnwindSafe};
+//! use thread_control::*;
+//!
+//! fn assert_unwind_safe<T: UnwindSafe + RefUnwindSafe>() {
+//! }
+//!
+//! fn main() {
+//!     assert_unwind_safe::<Flag>();
+//!     assert_unwind_safe::<Control>();
+//! }
+//! ```
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use futures::task::AtomicWaker;
+use {Control, Status};
+
+/// Future which resolves to the final status of the thread.
+#[derive(Debug)]
+pub struct Done {
+    control: Control,
+    waker: Arc<AtomicWaker>,
+}
+
+impl Future for Done {
+    type Output = Status;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Status> {
+        // Registered before the check to not miss a wake up in between
+        self.waker.register(cx.waker());
+        if self.control.is_done() {
+            Poll::Ready(self.control.status())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl Control {
+    /// Returns future which resolves to the final status
+    /// when the thread is done. Every future is woken,
+    /// also if there are many of them at once.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// extern crate futures;
+    /// extern crate thread_control;
+    ///
+    /// use std::thread;
+    /// use std::time::Duration;
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    ///     let (flag, control) = make_pair();
+    ///     let first = control.done();
+    ///     let waiter = thread::spawn(move || futures::executor::block_on(first));
+    ///     let second = control.done();
+    ///     thread::spawn(move || {
+    ///         thread::sleep(Duration::from_millis(50));
+    ///         drop(flag);
+    ///     });
+    ///     assert_eq!(futures::executor::block_on(second), Status::Finished);
+    ///     assert_eq!(waiter.join().unwrap(), Status::Finished);
+    /// }
+    /// ```
+    pub fn done(&self) -> Done {
+        let waker = Arc::new(AtomicWaker::new());
+        self.shared.wakers.lock().expect("wakers poisoned").push(Arc::downgrade(&waker));
+        Done {
+            control: self.clone(),
+            waker,
+        }
+    }
+}
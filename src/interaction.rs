@@ -0,0 +1,2485 @@
+//! Request/response interaction between threads.
+//!
+//! `Requester` deposits a request and waits for the response,
+//! `Responder` picks requests up and answers them. Every request gets
+//! a `u64` correlation id, so responses can't be mismatched even if
+//! the responder answers them out of order.
+//!
+//! Responses are moved to the requester, so neither side needs `Clone`.
+//! Only coalescing by `Requester::request_coalesced()` clones them,
+//! it delivers one response to many requesters.
+//!
+//! Example:
+//!
+//! ```rust
+//! use std::thread;
+//! use thread_control::*;
+//!
+//! fn main() {
+//!     let (requester, responder) = interaction_bounded::<u32, u32>(2);
+//!     let first = requester.submit(1).unwrap();
+//!     let second = requester.submit(2).unwrap();
+//!     let handle = thread::spawn(move || {
+//!         let (id_a, a) = responder.get_request().unwrap();
+//!         let (id_b, b) = responder.get_request().unwrap();
+//!         // Answer in the reverse order
+//!         responder.set_response(id_b, b * 10);
+//!         responder.set_response(id_a, a * 10);
+//!         responder
+//!     });
+//!     assert_eq!(requester.wait(second, None), Ok(20));
+//!     assert_eq!(requester.wait(first, None), Ok(10));
+//!     handle.join().unwrap();
+//! }
+//! ```
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+use std::error;
+use std::fmt;
+use std::hint;
+use std::iter;
+use std::marker::PhantomData;
+use std::mem;
+use std::thread;
+use std::sync::{Arc, Weak, Condvar, Mutex, MutexGuard, TryLockError};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use {spawn, Control, Flag};
+use counters;
+
+/// Reasons why a request can't be completed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// All slots are occupied by other requests.
+    Busy,
+    /// Response wasn't received in time.
+    Timeout,
+    /// Responder was dropped or panicked.
+    ThreadDead,
+    /// Waiting was cancelled by the flag.
+    Cancelled,
+    /// Slot of the request is in unexpected state.
+    WrongState,
+    /// Interaction was closed by `Service::stop()`.
+    Closed,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match *self {
+            Error::Busy => "interaction is busy",
+            Error::Timeout => "interaction timed out",
+            Error::ThreadDead => "responder thread is dead",
+            Error::Cancelled => "interaction was cancelled",
+            Error::WrongState => "interaction is in wrong state",
+            Error::Closed => "interaction is closed",
+        };
+        f.write_str(text)
+    }
+}
+
+impl error::Error for Error {
+}
+
+enum State<I, O> {
+    Free,
+    Request(I),
+    InProgress,
+    Response(O),
+    // Sentinel request of `Requester::ping()` and its acknowledgement
+    Ping,
+    Pong,
+}
+
+/// State of a request slot without its payload,
+/// recorded by interactions made with `interaction_debug()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateKind {
+    /// Slot is released.
+    Free,
+    /// Request is deposited.
+    Request,
+    /// Request is taken by the responder.
+    InProgress,
+    /// Response is set.
+    Response,
+    /// Ping is deposited.
+    Ping,
+    /// Ping is acknowledged.
+    Pong,
+}
+
+/// Result of `Responder::poll()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Poll<I> {
+    /// Request is taken with its correlation id.
+    Request(u64, I),
+    /// Nothing is pending.
+    Idle,
+    /// Nothing is pending, but a response wasn't consumed by its requester yet.
+    ResponsePending,
+    /// All requesters are dropped or the interaction is poisoned.
+    Disconnected,
+}
+
+struct Slot<I, O> {
+    id: u64,
+    state: State<I, O>,
+}
+
+struct Shared<I, O> {
+    next_id: u64,
+    slots: Vec<Slot<I, O>>,
+    // Pending requests by priority, older first on ties.
+    // Entries of released requests are skipped when popped.
+    pending: BinaryHeap<(u8, Reverse<u64>)>,
+    // Unconsumed responses are replaced instead of rejected
+    latest: bool,
+    // Requests in flight joined by `request_coalesced()`
+    coalesced: Vec<Coalesced<I, O>>,
+    // Last transitions of slots, kept only by `interaction_debug()`
+    history: VecDeque<(StateKind, Instant)>,
+    history_len: usize,
+}
+
+struct Coalesced<I, O> {
+    id: u64,
+    // Copy of the request to match identical ones
+    req: I,
+    followers: usize,
+    // Kept until all followers took a copy
+    response: Option<Result<O, Error>>,
+}
+
+impl<I, O> Shared<I, O> {
+    fn slot_mut(&mut self, id: u64) -> Option<&mut Slot<I, O>> {
+        self.slots.iter_mut().find(|slot| slot.id == id)
+    }
+
+    fn record(&mut self, kind: StateKind) {
+        if self.history_len == 0 {
+            return;
+        }
+        if self.history.len() == self.history_len {
+            self.history.pop_front();
+        }
+        self.history.push_back((kind, Instant::now()));
+    }
+}
+
+// How long a requester parks when the lock is held by another side
+const BUSY_PARK: Duration = Duration::from_millis(1);
+
+// How many times a waiting requester spins before it yields the thread
+const DEFAULT_SPINS: u32 = 64;
+
+// How long a spawned responder loop waits for a request between checks of its flag
+const LOOP_TICK: Duration = Duration::from_millis(10);
+
+// Id of a deposited request with the lock still held
+type Deposited<'a, I, O> = (u64, MutexGuard<'a, Shared<I, O>>);
+
+struct Inner<I, O> {
+    shared: Mutex<Shared<I, O>>,
+    // Requests given up while the lock was held, released by the next holder
+    abandoned: Mutex<Vec<u64>>,
+    // Notified when a request is deposited
+    arrived: Condvar,
+    // Notified when a response is set or the responder is dropped
+    responded: Condvar,
+    // Cleared when the last responder is dropped
+    responder_alive: AtomicBool,
+    // Number of existing responders including clones
+    responders: AtomicUsize,
+    // Set by `Service::stop()`, outstanding and new requests fail
+    closed: AtomicBool,
+    // Responses set by the responder
+    processed: AtomicUsize,
+}
+
+impl<I, O> Inner<I, O> {
+    fn check_open(&self) -> Result<(), Error> {
+        if self.closed.load(Ordering::Acquire) {
+            Err(Error::Closed)
+        } else if !self.responder_alive.load(Ordering::Acquire) {
+            Err(Error::ThreadDead)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, Shared<I, O>>, Error> {
+        let mut shared = self.shared.lock().map_err(|_| Error::ThreadDead)?;
+        self.release_abandoned(&mut shared);
+        Ok(shared)
+    }
+
+    fn try_lock(&self) -> Result<Option<MutexGuard<'_, Shared<I, O>>>, Error> {
+        match self.shared.try_lock() {
+            Ok(mut shared) => {
+                self.release_abandoned(&mut shared);
+                Ok(Some(shared))
+            },
+            Err(TryLockError::WouldBlock) => Ok(None),
+            Err(TryLockError::Poisoned(_)) => Err(Error::ThreadDead),
+        }
+    }
+
+    fn abandon(&self, id: u64) {
+        self.abandoned.lock().expect("abandoned poisoned").push(id);
+    }
+
+    // Pings are answered on the way
+    fn take_request(&self, shared: &mut Shared<I, O>) -> Option<(u64, I)> {
+        let mut taken = None;
+        let mut ponged = false;
+        while let Some((_, Reverse(id))) = shared.pending.pop() {
+            if let Some(slot) = shared.slot_mut(id) {
+                match mem::replace(&mut slot.state, State::InProgress) {
+                    State::Request(req) => {
+                        shared.record(StateKind::InProgress);
+                        taken = Some((id, req));
+                        break;
+                    },
+                    State::Ping => {
+                        slot.state = State::Pong;
+                        shared.record(StateKind::Pong);
+                        ponged = true;
+                    },
+                    state => {
+                        slot.state = state;
+                    },
+                }
+            }
+        }
+        if ponged {
+            self.responded.notify_all();
+        }
+        taken
+    }
+
+    fn release_abandoned(&self, shared: &mut Shared<I, O>) {
+        let mut abandoned = self.abandoned.lock().expect("abandoned poisoned");
+        let mut released = false;
+        for id in abandoned.drain(..) {
+            if let Some(slot) = shared.slot_mut(id) {
+                slot.state = State::Free;
+                shared.record(StateKind::Free);
+                released = true;
+            }
+        }
+        if released {
+            // Requesters waiting for freed slots get `WrongState`
+            self.responded.notify_all();
+        }
+    }
+}
+
+/// Side of interaction which sends requests.
+pub struct Requester<I, O> {
+    data: Weak<Inner<I, O>>,
+    requesters: Arc<AtomicUsize>,
+    spins: u32,
+    // Used by `request_default()`, see `interaction_with_timeout()`
+    default_timeout: Option<Duration>,
+}
+
+impl<I, O> Clone for Requester<I, O> {
+    fn clone(&self) -> Self {
+        self.requesters.fetch_add(1, Ordering::Relaxed);
+        Requester {
+            data: self.data.clone(),
+            requesters: self.requesters.clone(),
+            spins: self.spins,
+            default_timeout: self.default_timeout,
+        }
+    }
+}
+
+impl<I, O> Drop for Requester<I, O> {
+    fn drop(&mut self) {
+        if self.requesters.fetch_sub(1, Ordering::AcqRel) == 1 {
+            if let Some(data) = self.data.upgrade() {
+                // Wakes `Responder::run()` to notice the disconnect,
+                // taken to not notify between its check and its wait
+                drop(data.shared.lock());
+                data.arrived.notify_all();
+            }
+        }
+    }
+}
+
+impl<I, O> fmt::Debug for Requester<I, O> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Requester").finish()
+    }
+}
+
+impl<I, O> Requester<I, O> {
+    /// Sets how many times waiting for a response spins with
+    /// `std::hint::spin_loop()` before it blocks until the response
+    /// is set. Spinning reduces latency with fast responders,
+    /// `0` makes waiting block right away.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    ///     let (requester, responder) = interaction::<u32, u32>();
+    ///     let requester = requester.with_spins(0);
+    ///     let control = responder.spawn_loop(|req| req * 2);
+    ///     assert_eq!(requester.request(4, None), Ok(8));
+    ///     control.stop();
+    /// }
+    /// ```
+    pub fn with_spins(mut self, spins: u32) -> Self {
+        self.spins = spins;
+        self
+    }
+
+    /// Sends request and waits for the response.
+    /// Waits forever if `timeout` is `None`.
+    ///
+    /// Neither side busy-waits: the requester blocks until the response
+    /// is set and a responder blocked in `get_request_timeout()`
+    /// is woken by the request.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use std::fs;
+    /// use std::thread;
+    /// use std::time::Duration;
+    /// use thread_control::*;
+    ///
+    /// // CPU time spent by the current thread in clock ticks
+    /// fn cpu_ticks() -> u64 {
+    ///     let stat = fs::read_to_string("/proc/thread-self/stat").unwrap();
+    ///     // Fields after the command name, the state is the first of them
+    ///     let fields: Vec<&str> = stat.rsplit(')').next().unwrap().split_whitespace().collect();
+    ///     fields[11].parse::<u64>().unwrap() + fields[12].parse::<u64>().unwrap()
+    /// }
+    ///
+    /// fn main() {
+    /// #     // Timing assertions don't hold under the slow interpreter
+    /// #     if cfg!(miri) { return; }
+    ///     // The thread CPU time is read from procfs
+    ///     if !cfg!(target_os = "linux") {
+    ///         return;
+    ///     }
+    ///     let idle = Duration::from_millis(300);
+    ///     let (requester, responder) = interaction::<u32, u32>();
+    ///     let requester = requester.with_spins(0);
+    ///     let handle = thread::spawn(move || {
+    ///         let before = cpu_ticks();
+    ///         // Blocked until the request comes
+    ///         let (id, req) = responder.get_request_timeout(None).unwrap();
+    ///         let spent = cpu_ticks() - before;
+    ///         thread::sleep(idle);
+    ///         responder.set_response(id, req * 2);
+    ///         spent
+    ///     });
+    ///     thread::sleep(idle);
+    ///     let before = cpu_ticks();
+    ///     // Blocked until the response comes
+    ///     assert_eq!(requester.request(1, None), Ok(2));
+    ///     let requester_spent = cpu_ticks() - before;
+    ///     let responder_spent = handle.join().unwrap();
+    ///     // Spinning through the idle time would take about 30 ticks
+    ///     assert!(requester_spent < 10, "requester spent {} ticks", requester_spent);
+    ///     assert!(responder_spent < 10, "responder spent {} ticks", responder_spent);
+    ///     // The responder is dropped with its thread
+    ///     assert!(requester.request(3, None).is_err());
+    /// }
+    /// ```
+    pub fn request(&self, req: I, timeout: Option<Duration>) -> Result<O, Error> {
+        self.request_take(req, timeout)
+    }
+
+    /// Sends request and moves the single response out. Same as `request()`,
+    /// named for call sites with responses which aren't `Clone`.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use std::thread;
+    /// use thread_control::*;
+    ///
+    /// // Neither `Clone` nor `PartialEq`
+    /// struct Unique(Vec<u8>);
+    ///
+    /// fn main() {
+    ///     let (requester, responder) = interaction::<u8, Unique>();
+    ///     let control = responder.spawn_loop(|req| Unique(vec![req; 3]));
+    ///     let Unique(bytes) = requester.request_take(7, None).unwrap();
+    ///     assert_eq!(bytes, vec![7, 7, 7]);
+    ///     control.stop();
+    ///     control.join_result().unwrap();
+    /// }
+    /// ```
+    pub fn request_take(&self, req: I, timeout: Option<Duration>) -> Result<O, Error> {
+        self.send_and_wait(req, timeout).map(|(resp, _)| resp)
+    }
+
+    /// Keeps the state of the interaction for a batch of requests,
+    /// so they don't upgrade a weak reference each.
+    /// Returns `None` if the responder is already dropped.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    ///     let (requester, responder) = interaction::<u32, u32>();
+    ///     let control = responder.spawn_loop(|req| req + 1);
+    ///     let pinned = requester.pin().unwrap();
+    ///     for req in 0..100 {
+    ///         assert_eq!(pinned.request(req, None), Ok(req + 1));
+    ///     }
+    ///     drop(pinned);
+    ///     control.stop();
+    ///     control.join_result().unwrap();
+    ///     assert!(requester.pin().is_none());
+    /// }
+    /// ```
+    pub fn pin(&self) -> Option<PinnedRequester<'_, I, O>> {
+        self.upgrade().ok().map(|data| PinnedRequester {
+            requester: self,
+            data,
+        })
+    }
+
+    /// Sends request and waits for the response up to the timeout set by
+    /// `interaction_with_timeout()`. Waits forever for other interactions.
+    /// Pass a timeout to `request()` to override it.
+    pub fn request_default(&self, req: I) -> Result<O, Error> {
+        self.send_and_wait(req, self.default_timeout).map(|(resp, _)| resp)
+    }
+
+    /// Sends request and waits for the response like `request()`,
+    /// and returns it with the time from the deposit to its receipt.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use std::thread;
+    /// use std::time::Duration;
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    ///     let (requester, responder) = interaction::<u32, u32>();
+    ///     let control = responder.spawn_loop(|req| {
+    ///         thread::sleep(Duration::from_millis(50));
+    ///         req * 2
+    ///     });
+    ///     let (resp, elapsed) = requester.request_timed(2, None).unwrap();
+    ///     assert_eq!(resp, 4);
+    ///     assert!(elapsed >= Duration::from_millis(50));
+    ///     assert!(elapsed < Duration::from_secs(10));
+    ///     control.stop();
+    /// }
+    /// ```
+    pub fn request_timed(&self, req: I, timeout: Option<Duration>) -> Result<(O, Duration), Error> {
+        self.send_and_wait(req, timeout)
+    }
+
+    fn send_and_wait(&self, req: I, timeout: Option<Duration>) -> Result<(O, Duration), Error> {
+        // Keeps the state between sending and waiting
+        let data = self.upgrade()?;
+        // The lock taken to send is kept for the first check of the response
+        let (id, shared) = self.deposit_locked(&data, req, 0).map_err(|(err, _)| err)?;
+        let now = Instant::now();
+        self.wait_held(&data, Some(shared), id, timeout, None, || None)
+            .map(|resp| (resp, now.elapsed()))
+    }
+
+    /// Sends request and waits for the response like `request()`,
+    /// but returns `fallback` instead of any error.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use std::time::Duration;
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    ///     let (requester, _responder) = interaction::<u32, &str>();
+    ///     let timeout = Some(Duration::from_millis(10));
+    ///     assert_eq!(requester.request_or(1, timeout, "unknown"), "unknown");
+    /// }
+    /// ```
+    pub fn request_or(&self, req: I, timeout: Option<Duration>, fallback: O) -> O {
+        match self.request(req, timeout) {
+            Ok(resp) => resp,
+            Err(_err) => {
+                #[cfg(feature = "tracing")]
+                warn!("request failed, fallback is used: {}", _err);
+                fallback
+            },
+        }
+    }
+
+    /// Sends all requests at once and collects responses
+    /// tagged by correlation ids of the requests.
+    /// Requests which don't fit into free slots fail with `Busy`.
+    pub fn request_all<T>(&self, reqs: T, timeout: Option<Duration>) -> Vec<Result<(u64, O), Error>>
+        where T: IntoIterator<Item = I>
+    {
+        // Keeps the state between sending and waiting
+        let _data = self.data.upgrade();
+        let submitted: Vec<_> = reqs.into_iter().map(|req| self.submit(req)).collect();
+        submitted.into_iter().map(|res| {
+            res.and_then(|id| self.wait(id, timeout).map(|resp| (id, resp)))
+        }).collect()
+    }
+
+    /// Sends request with priority `prio` and waits for the response.
+    /// The responder gets requests with higher priority first.
+    /// Waits forever if `timeout` is `None`.
+    pub fn request_prioritized(&self, req: I, prio: u8, timeout: Option<Duration>) -> Result<O, Error> {
+        // Keeps the state between sending and waiting
+        let _data = self.upgrade()?;
+        let id = self.submit_prioritized(req, prio)?;
+        self.wait(id, timeout)
+    }
+
+    /// Checks the responder takes requests without sending a real one
+    /// and returns the round-trip time. Any responder which takes requests
+    /// acknowledges it, so it fails with `Timeout` if the responder loop
+    /// hangs and with `ThreadDead` if the responder is dropped.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use std::thread;
+    /// use std::time::Duration;
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    ///     let (requester, responder) = interaction::<u32, u32>();
+    ///     let control = responder.spawn_loop(|req| req);
+    ///     assert!(requester.ping(Some(Duration::from_secs(10))).is_ok());
+    ///     control.stop();
+    ///     control.join_result().unwrap();
+    ///
+    ///     let (requester, responder) = interaction::<u32, u32>();
+    ///     thread::spawn(move || {
+    ///         // Hung loop which never takes requests
+    ///         thread::sleep(Duration::from_secs(1));
+    ///         responder
+    ///     });
+    ///     let result = requester.ping(Some(Duration::from_millis(50)));
+    ///     assert_eq!(result, Err(interaction::Error::Timeout));
+    /// }
+    /// ```
+    pub fn ping(&self, timeout: Option<Duration>) -> Result<Duration, Error> {
+        let now = Instant::now();
+        let data = self.upgrade()?;
+        let id = {
+            let mut shared = data.lock()?;
+            let id = shared.next_id;
+            let slot = shared.slots.iter_mut()
+                .find(|slot| matches!(slot.state, State::Free))
+                .ok_or(Error::Busy)?;
+            slot.id = id;
+            slot.state = State::Ping;
+            shared.record(StateKind::Ping);
+            shared.next_id += 1;
+            // Not queued behind real requests
+            shared.pending.push((u8::MAX, Reverse(id)));
+            data.arrived.notify_all();
+            id
+        };
+        // The state is kept, a pong set before the responder was dropped is still taken
+        let mut shared = data.lock()?;
+        loop {
+            {
+                let slot = shared.slot_mut(id).ok_or(Error::WrongState)?;
+                match slot.state {
+                    State::Pong => {
+                        slot.state = State::Free;
+                        shared.record(StateKind::Free);
+                        return Ok(now.elapsed());
+                    },
+                    State::Ping => {
+                        if timeout.is_some_and(|timeout| now.elapsed() >= timeout) {
+                            slot.state = State::Free;
+                            shared.record(StateKind::Free);
+                            return Err(Error::Timeout);
+                        }
+                        if let Err(err) = data.check_open() {
+                            slot.state = State::Free;
+                            shared.record(StateKind::Free);
+                            return Err(err);
+                        }
+                    },
+                    _ => {
+                        return Err(Error::WrongState);
+                    },
+                }
+            }
+            // Blocks until the pong is set, but wakes up for the deadline
+            shared = match timeout {
+                Some(timeout) => {
+                    let wait = timeout.saturating_sub(now.elapsed());
+                    data.responded.wait_timeout(shared, wait).map_err(|_| Error::ThreadDead)?.0
+                },
+                None => {
+                    data.responded.wait(shared).map_err(|_| Error::ThreadDead)?
+                },
+            };
+            data.release_abandoned(&mut shared);
+        }
+    }
+
+    /// Deposits request into a free slot and returns its correlation id
+    /// without waiting for the response. The request has the lowest priority.
+    pub fn submit(&self, req: I) -> Result<u64, Error> {
+        self.submit_prioritized(req, 0)
+    }
+
+    /// Deposits request with priority `prio` into a free slot and returns
+    /// its correlation id without waiting for the response.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    ///     let (requester, responder) = interaction_bounded::<&str, ()>(3);
+    ///     requester.submit_prioritized("low", 1).unwrap();
+    ///     requester.submit_prioritized("high", 200).unwrap();
+    ///     requester.submit_prioritized("medium", 100).unwrap();
+    ///     let order: Vec<_> = (0..3).map(|_| responder.get_request().unwrap().1).collect();
+    ///     assert_eq!(order, vec!["high", "medium", "low"]);
+    /// }
+    /// ```
+    pub fn submit_prioritized(&self, req: I, prio: u8) -> Result<u64, Error> {
+        self.deposit(req, prio).map_err(|(err, _)| err)
+    }
+
+    /// Deposits request like `submit()`, but returns the request back
+    /// with the error if it can't be deposited (e.g. on `Busy`).
+    pub fn try_submit(&self, req: I) -> Result<u64, (Error, I)> {
+        self.deposit(req, 0)
+    }
+
+    // The state outlives the responder if others keep it, e.g. `Service`
+    fn upgrade(&self) -> Result<Arc<Inner<I, O>>, Error> {
+        let data = self.data.upgrade().ok_or(Error::ThreadDead)?;
+        data.check_open()?;
+        Ok(data)
+    }
+
+    fn deposit(&self, req: I, prio: u8) -> Result<u64, (Error, I)> {
+        let data = match self.upgrade() {
+            Ok(data) => data,
+            Err(err) => return Err((err, req)),
+        };
+        self.deposit_locked(&data, req, prio).map(|(id, _)| id)
+    }
+
+    fn deposit_locked<'a>(&self, data: &'a Inner<I, O>, req: I, prio: u8)
+        -> Result<Deposited<'a, I, O>, (Error, I)>
+    {
+        match data.lock() {
+            Ok(shared) => self.deposit_held(data, shared, req, prio),
+            Err(err) => Err((err, req)),
+        }
+    }
+
+    fn deposit_held<'a>(&self, data: &'a Inner<I, O>, mut shared: MutexGuard<'a, Shared<I, O>>, req: I, prio: u8)
+        -> Result<Deposited<'a, I, O>, (Error, I)>
+    {
+        let id = shared.next_id;
+        match shared.slots.iter_mut().find(|slot| matches!(slot.state, State::Free)) {
+            Some(slot) => {
+                slot.id = id;
+                slot.state = State::Request(req);
+            },
+            None => {
+                return Err((Error::Busy, req));
+            },
+        }
+        shared.record(StateKind::Request);
+        shared.next_id += 1;
+        shared.pending.push((prio, Reverse(id)));
+        data.arrived.notify_all();
+        counters::request();
+        Ok((id, shared))
+    }
+
+    /// Waits for the response to the request with `id`.
+    /// The slot is released if the timeout expires.
+    /// The timeout is respected even if the lock is held by another side.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use std::thread;
+    /// use std::time::{Duration, Instant};
+    /// use thread_control::*;
+    ///
+    /// struct SlowDrop;
+    ///
+    /// impl Drop for SlowDrop {
+    ///     fn drop(&mut self) {
+    ///         thread::sleep(Duration::from_millis(300));
+    ///     }
+    /// }
+    ///
+    /// fn main() {
+    /// #     // Timing assertions don't hold under the slow interpreter
+    /// #     if cfg!(miri) { return; }
+    ///     let (requester, _responder) = interaction_bounded::<SlowDrop, ()>(2);
+    ///     let other = requester.clone();
+    ///     let slow = other.submit(SlowDrop).unwrap();
+    ///     let id = requester.submit(SlowDrop).unwrap();
+    ///     // Releasing the slot drops the request while the lock is held
+    ///     thread::spawn(move || other.wait(slow, Some(Duration::from_millis(10))));
+    ///     thread::sleep(Duration::from_millis(50));
+    ///     let started = Instant::now();
+    ///     let result = requester.wait(id, Some(Duration::from_millis(50)));
+    ///     assert_eq!(result, Err(interaction::Error::Timeout));
+    ///     assert!(started.elapsed() < Duration::from_millis(200));
+    /// }
+    /// ```
+    pub fn wait(&self, id: u64, timeout: Option<Duration>) -> Result<O, Error> {
+        self.wait_extended(id, timeout, None, || None)
+    }
+
+    /// Sends request and waits for the response up to `initial`.
+    /// When the timeout expires `on_timeout` is called, it may return
+    /// a duration to keep waiting or `None` to give up with `Timeout`.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use std::thread;
+    /// use std::time::Duration;
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    ///     let (requester, responder) = interaction::<u32, u32>();
+    ///     thread::spawn(move || {
+    ///         thread::sleep(Duration::from_millis(100));
+    ///         responder.run(|req| req + 1);
+    ///     });
+    ///     let mut extensions = 0;
+    ///     let initial = Duration::from_millis(10);
+    ///     let result = requester.request_with_extend(1, initial, || {
+    ///         extensions += 1;
+    ///         if extensions == 1 { Some(Duration::from_secs(10)) } else { None }
+    ///     });
+    ///     assert_eq!(result, Ok(2));
+    ///     assert_eq!(extensions, 1);
+    /// }
+    /// ```
+    pub fn request_with_extend<F>(&self, req: I, initial: Duration, on_timeout: F) -> Result<O, Error>
+        where F: FnMut() -> Option<Duration>
+    {
+        // Keeps the state between sending and waiting
+        let _data = self.upgrade()?;
+        let id = self.submit(req)?;
+        self.wait_extended(id, Some(initial), None, on_timeout)
+    }
+
+    /// Sends request and waits for the response like `request()`,
+    /// but gives up with `Cancelled` when `cancel` is stopped.
+    /// The slot of the request is released in that case.
+    ///
+    /// # Panics
+    ///
+    /// This method panics, if interrupt flag of `cancel` was set.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use std::thread;
+    /// use std::time::Duration;
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    ///     let (requester, _responder) = interaction::<u32, u32>();
+    ///     let (cancel, control) = make_pair();
+    ///     thread::spawn(move || {
+    ///         thread::sleep(Duration::from_millis(50));
+    ///         control.stop();
+    ///     });
+    ///     let result = requester.request_cancellable(1, &cancel, None);
+    ///     assert_eq!(result, Err(interaction::Error::Cancelled));
+    ///     // The slot is free again
+    ///     assert!(requester.submit(2).is_ok());
+    /// }
+    /// ```
+    pub fn request_cancellable(&self, req: I, cancel: &Flag, timeout: Option<Duration>) -> Result<O, Error> {
+        // Keeps the state between sending and waiting
+        let _data = self.upgrade()?;
+        let id = self.submit(req)?;
+        self.wait_extended(id, timeout, Some(cancel), || None)
+    }
+
+    fn wait_extended<F>(&self, id: u64, timeout: Option<Duration>, cancel: Option<&Flag>, on_timeout: F) -> Result<O, Error>
+        where F: FnMut() -> Option<Duration>
+    {
+        // The state is kept, a response set before the responder was dropped is still taken
+        let data = self.data.upgrade().ok_or(Error::ThreadDead)?;
+        self.wait_held(&data, None, id, timeout, cancel, on_timeout)
+    }
+
+    // Starts with the `held` lock if the caller has one
+    fn wait_held<F>(&self, data: &Inner<I, O>, mut held: Option<MutexGuard<'_, Shared<I, O>>>, id: u64,
+                    mut timeout: Option<Duration>, cancel: Option<&Flag>, mut on_timeout: F) -> Result<O, Error>
+        where F: FnMut() -> Option<Duration>
+    {
+        let now = Instant::now();
+        let mut spins = 0;
+        loop {
+            if cancel.is_some_and(|cancel| !cancel.alive()) {
+                drop(held.take());
+                data.abandon(id);
+                return Err(Error::Cancelled);
+            }
+            let shared = match held.take() {
+                Some(shared) => Some(shared),
+                None => data.try_lock()?,
+            };
+            match shared {
+                Some(mut shared) => {
+                    let slot = shared.slot_mut(id).ok_or(Error::WrongState)?;
+                    match mem::replace(&mut slot.state, State::Free) {
+                        State::Response(resp) => {
+                            shared.record(StateKind::Free);
+                            return Ok(resp);
+                        },
+                        State::Free => {
+                            return Err(Error::WrongState);
+                        },
+                        state => {
+                            slot.state = state;
+                        },
+                    }
+                    if timeout.is_some_and(|timeout| now.elapsed() >= timeout) {
+                        // The lock is kept to not miss a response set meanwhile
+                        match on_timeout() {
+                            Some(extra) => {
+                                timeout = timeout.map(|timeout| timeout + extra);
+                            },
+                            None => {
+                                slot.state = State::Free;
+                                shared.record(StateKind::Free);
+                                return Err(Error::Timeout);
+                            },
+                        }
+                    }
+                    // Checked under the lock, the responder and `Service::stop()`
+                    // notify after taking it
+                    if data.closed.load(Ordering::Acquire) {
+                        slot.state = State::Free;
+                        shared.record(StateKind::Free);
+                        return Err(Error::Closed);
+                    }
+                    if !data.responder_alive.load(Ordering::Acquire) {
+                        return Err(Error::ThreadDead);
+                    }
+                    // The response of a fast responder is caught without a context switch
+                    if spins < self.spins {
+                        spins += 1;
+                        drop(shared);
+                        hint::spin_loop();
+                        continue;
+                    }
+                    // Blocks until a response is set, but wakes up for the deadline
+                    // and regularly to check the cancel flag
+                    let mut wait = timeout.map(|timeout| timeout.saturating_sub(now.elapsed()));
+                    if cancel.is_some() {
+                        wait = Some(wait.map_or(LOOP_TICK, |wait| wait.min(LOOP_TICK)));
+                    }
+                    match wait {
+                        Some(wait) => {
+                            drop(data.responded.wait_timeout(shared, wait).map_err(|_| Error::ThreadDead)?);
+                        },
+                        None => {
+                            drop(data.responded.wait(shared).map_err(|_| Error::ThreadDead)?);
+                        },
+                    }
+                    continue;
+                },
+                None => {
+                    if let Some(ref mut timeout) = timeout {
+                        let elapsed = now.elapsed();
+                        if elapsed >= *timeout {
+                            if let Some(extra) = on_timeout() {
+                                *timeout += extra;
+                                continue;
+                            }
+                            data.abandon(id);
+                            return Err(Error::Timeout);
+                        }
+                        // Don't spin on the held lock, but wake up near the deadline
+                        thread::park_timeout((*timeout - elapsed).min(BUSY_PARK));
+                        continue;
+                    }
+                },
+            }
+            if spins < self.spins {
+                spins += 1;
+                hint::spin_loop();
+            } else {
+                thread::yield_now();
+            }
+        }
+    }
+}
+
+impl<I: Clone + PartialEq, O: Clone> Requester<I, O> {
+    /// Sends request and waits up to `window` for the response like
+    /// `request()`, but joins an equal request already sent by this
+    /// method if there is one in flight, instead of sending a new one.
+    /// Joined requests get the result of the sent one, also its error.
+    /// Use it for identical requests to reduce load of expensive
+    /// responders.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use std::sync::{Arc, Barrier};
+    /// use std::thread;
+    /// use std::time::Duration;
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    ///     let (requester, responder) = interaction_bounded::<u32, u32>(2);
+    ///     let window = Duration::from_secs(10);
+    ///     let waiters: Vec<_> = [1, 1, 2].iter().map(|&req| {
+    ///         let requester = requester.clone();
+    ///         let waiter = thread::spawn(move || requester.request_coalesced(req, window));
+    ///         thread::sleep(Duration::from_millis(50));
+    ///         waiter
+    ///     }).collect();
+    ///     // Only different requests are sent
+    ///     let mut reqs = Vec::new();
+    ///     while let Some((id, req)) = responder.get_request() {
+    ///         responder.set_response(id, req * 10);
+    ///         reqs.push(req);
+    ///     }
+    ///     assert_eq!(reqs, vec![1, 2]);
+    ///     let results: Vec<_> = waiters.into_iter().map(|waiter| waiter.join().unwrap()).collect();
+    ///     assert_eq!(results, vec![Ok(10), Ok(10), Ok(20)]);
+    ///
+    ///     // The responder hangs, but the window is respected
+    ///     let result = requester.request_coalesced(3, Duration::from_millis(50));
+    ///     assert_eq!(result, Err(interaction::Error::Timeout));
+    ///
+    /// #     // Timing assertions don't hold under the slow interpreter
+    /// #     if cfg!(miri) { return; }
+    ///     // Equal requests sent at once are sent only once, a duplicate
+    ///     // would be `Busy` with a single slot
+    ///     let (requester, responder) = interaction::<u32, u32>();
+    ///     let handled = thread::spawn(move || {
+    ///         let mut handled = 0;
+    ///         responder.run(|req| {
+    ///             handled += 1;
+    ///             thread::sleep(Duration::from_millis(50));
+    ///             req * 10
+    ///         });
+    ///         handled
+    ///     });
+    ///     let rounds = 20;
+    ///     for round in 0..rounds {
+    ///         let barrier = Arc::new(Barrier::new(8));
+    ///         let waiters: Vec<_> = (0..8).map(|_| {
+    ///             let requester = requester.clone();
+    ///             let barrier = barrier.clone();
+    ///             thread::spawn(move || {
+    ///                 barrier.wait();
+    ///                 requester.request_coalesced(round, window)
+    ///             })
+    ///         }).collect();
+    ///         for waiter in waiters {
+    ///             assert_eq!(waiter.join().unwrap(), Ok(round * 10));
+    ///         }
+    ///     }
+    ///     drop(requester);
+    ///     assert_eq!(handled.join().unwrap(), rounds);
+    /// }
+    /// ```
+    pub fn request_coalesced(&self, req: I, window: Duration) -> Result<O, Error> {
+        let data = self.upgrade()?;
+        let mut shared = data.lock()?;
+        let pending = shared.coalesced.iter_mut()
+            .find(|coalesced| coalesced.response.is_none() && coalesced.req == req);
+        if let Some(coalesced) = pending {
+            coalesced.followers += 1;
+            let id = coalesced.id;
+            drop(shared);
+            return self.follow(&data, id, window);
+        }
+        // Deposited under the lock of the lookup, so equal requests racing with it join it
+        let key = req.clone();
+        let (id, mut shared) = self.deposit_held(&data, shared, req, 0).map_err(|(err, _)| err)?;
+        shared.coalesced.push(Coalesced { id, req: key, followers: 0, response: None });
+        let result = self.wait_held(&data, Some(shared), id, Some(window), None, || None);
+        let mut shared = data.lock()?;
+        if let Some(pos) = shared.coalesced.iter().position(|coalesced| coalesced.id == id) {
+            if shared.coalesced[pos].followers > 0 {
+                shared.coalesced[pos].response = Some(result.clone());
+                data.responded.notify_all();
+            } else {
+                shared.coalesced.remove(pos);
+            }
+        }
+        result
+    }
+
+    fn follow(&self, data: &Inner<I, O>, id: u64, window: Duration) -> Result<O, Error> {
+        let now = Instant::now();
+        let mut shared = data.lock()?;
+        loop {
+            {
+                let pos = shared.coalesced.iter()
+                    .position(|coalesced| coalesced.id == id)
+                    .ok_or(Error::WrongState)?;
+                let expired = now.elapsed() >= window;
+                let coalesced = &mut shared.coalesced[pos];
+                let resp = coalesced.response.clone();
+                if resp.is_some() || expired {
+                    coalesced.followers -= 1;
+                }
+                if coalesced.followers == 0 && coalesced.response.is_some() {
+                    shared.coalesced.remove(pos);
+                }
+                match resp {
+                    Some(result) => return result,
+                    None if expired => return Err(Error::Timeout),
+                    None => { },
+                }
+            }
+            // Blocks until the leader shares the response, but wakes up for the deadline
+            let wait = window.saturating_sub(now.elapsed());
+            shared = data.responded.wait_timeout(shared, wait).map_err(|_| Error::ThreadDead)?.0;
+            data.release_abandoned(&mut shared);
+        }
+    }
+}
+
+/// Requester which keeps the state of the interaction,
+/// made by `Requester::pin()`.
+pub struct PinnedRequester<'a, I: 'a, O: 'a> {
+    requester: &'a Requester<I, O>,
+    data: Arc<Inner<I, O>>,
+}
+
+impl<'a, I, O> fmt::Debug for PinnedRequester<'a, I, O> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PinnedRequester").finish()
+    }
+}
+
+impl<'a, I, O> PinnedRequester<'a, I, O> {
+    /// Sends request and waits for the response like `Requester::request()`.
+    pub fn request(&self, req: I, timeout: Option<Duration>) -> Result<O, Error> {
+        self.data.check_open()?;
+        let (id, shared) = self.requester.deposit_locked(&self.data, req, 0).map_err(|(err, _)| err)?;
+        self.requester.wait_held(&self.data, Some(shared), id, timeout, None, || None)
+    }
+}
+
+/// Extension methods of `Requester`.
+pub trait RequesterExt<I, O> {
+    /// Sends request like `Requester::request()`, but makes up to `attempts`
+    /// tries while the interaction is `Busy`, sleeping `backoff` between them.
+    /// Other errors are returned immediately.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use std::thread;
+    /// use std::time::{Duration, Instant};
+    /// use thread_control::*;
+    /// use thread_control::interaction::RequesterExt;
+    ///
+    /// fn main() {
+    ///     let (requester, responder) = interaction::<u32, u32>();
+    ///     let other = requester.clone();
+    ///     let id = other.submit(1).unwrap();
+    ///     // The slot is busy until the other requester gets its response
+    ///     thread::spawn(move || other.wait(id, None));
+    ///     thread::spawn(move || {
+    ///         thread::sleep(Duration::from_millis(50));
+    ///         responder.run(|req| req * 10);
+    ///     });
+    ///     let started = Instant::now();
+    ///     let backoff = Duration::from_millis(30);
+    ///     assert_eq!(requester.request_retry(2, 5, backoff, None), Ok(20));
+    ///     assert!(started.elapsed() >= Duration::from_millis(50));
+    /// }
+    /// ```
+    fn request_retry(&self, req: I, attempts: usize, backoff: Duration, timeout: Option<Duration>) -> Result<O, Error>;
+}
+
+impl<I, O> RequesterExt<I, O> for Requester<I, O> {
+    fn request_retry(&self, mut req: I, attempts: usize, backoff: Duration, timeout: Option<Duration>) -> Result<O, Error> {
+        for attempt in 1.. {
+            match self.try_submit(req) {
+                Ok(id) => {
+                    return self.wait(id, timeout);
+                },
+                Err((Error::Busy, back)) if attempt < attempts => {
+                    req = back;
+                    thread::sleep(backoff);
+                },
+                Err((err, _)) => {
+                    return Err(err);
+                },
+            }
+        }
+        unreachable!();
+    }
+}
+
+/// Side of interaction which handles requests.
+///
+/// Clones take requests from the same queue, so several threads can
+/// share the load. Every request is taken by one of them and its
+/// response reaches the requester by the correlation id.
+/// Requesters get `ThreadDead` when the last clone is dropped.
+///
+/// Example:
+///
+/// ```rust
+/// use std::sync::{Arc, Barrier};
+/// use std::thread;
+/// use thread_control::*;
+///
+/// fn main() {
+///     let (requester, responder) = interaction_bounded::<u32, (u32, usize)>(4);
+///     let barrier = Arc::new(Barrier::new(2));
+///     let handles: Vec<_> = (0..2).map(|index| {
+///         let responder = responder.clone();
+///         let barrier = barrier.clone();
+///         thread::spawn(move || {
+///             let (id, req) = loop {
+///                 if let Some(pair) = responder.get_request() {
+///                     break pair;
+///                 }
+///                 thread::yield_now();
+///             };
+///             // Both responders hold a request before any answers
+///             barrier.wait();
+///             responder.set_response(id, (req * 10, index));
+///             responder.run(|req| (req * 10, index));
+///         })
+///     }).collect();
+///     drop(responder);
+///     let ids: Vec<_> = (0..4).map(|req| requester.submit(req).unwrap()).collect();
+///     let mut served = Vec::new();
+///     for (req, id) in (0..4).zip(ids) {
+///         let (resp, index) = requester.wait(id, None).unwrap();
+///         assert_eq!(resp, req * 10);
+///         served.push(index);
+///     }
+///     drop(requester);
+///     for handle in handles {
+///         handle.join().unwrap();
+///     }
+///     served.sort();
+///     served.dedup();
+///     assert_eq!(served, vec![0, 1]);
+/// }
+/// ```
+pub struct Responder<I, O> {
+    data: Arc<Inner<I, O>>,
+    requesters: Arc<AtomicUsize>,
+}
+
+impl<I, O> Clone for Responder<I, O> {
+    fn clone(&self) -> Self {
+        self.data.responders.fetch_add(1, Ordering::Relaxed);
+        Responder {
+            data: self.data.clone(),
+            requesters: self.requesters.clone(),
+        }
+    }
+}
+
+impl<I, O> Drop for Responder<I, O> {
+    fn drop(&mut self) {
+        if self.data.responders.fetch_sub(1, Ordering::AcqRel) > 1 {
+            // Other clones still answer
+            return;
+        }
+        self.data.responder_alive.store(false, Ordering::Release);
+        // Taken to not notify between the check and the wait of a requester
+        drop(self.data.shared.lock());
+        self.data.responded.notify_all();
+    }
+}
+
+impl<I, O> fmt::Debug for Responder<I, O> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Responder").finish()
+    }
+}
+
+impl<I, O> Responder<I, O> {
+    /// Takes the pending request with the highest priority (the oldest
+    /// one of them) together with its correlation id.
+    /// Returns `None` if there is no pending request.
+    pub fn get_request(&self) -> Option<(u64, I)> {
+        let mut shared = self.data.lock().expect("interaction poisoned");
+        self.data.take_request(&mut shared)
+    }
+
+    /// Takes all pending requests, answers them with `f` in the order
+    /// of `get_request()` and returns the number of handled requests.
+    /// Responses to requests which requesters gave up are discarded.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    ///     let (requester, responder) = interaction_bounded::<u32, u32>(4);
+    ///     let ids: Vec<_> = (1..4).map(|req| requester.submit(req).unwrap()).collect();
+    ///     let mut handled = Vec::new();
+    ///     let count = responder.respond_to_all(|req| {
+    ///         handled.push(req);
+    ///         req * 10
+    ///     });
+    ///     assert_eq!(count, 3);
+    ///     assert_eq!(handled, vec![1, 2, 3]);
+    ///     let responses: Vec<_> = ids.into_iter().map(|id| requester.wait(id, None)).collect();
+    ///     assert_eq!(responses, vec![Ok(10), Ok(20), Ok(30)]);
+    ///     assert_eq!(responder.respond_to_all(|req| req), 0);
+    /// }
+    /// ```
+    pub fn respond_to_all<F>(&self, mut f: F) -> usize
+        where F: FnMut(I) -> O
+    {
+        // Taken under one lock, handled without it
+        let taken: Vec<_> = {
+            let mut shared = self.data.lock().expect("interaction poisoned");
+            iter::from_fn(|| self.data.take_request(&mut shared)).collect()
+        };
+        let count = taken.len();
+        for (id, req) in taken {
+            let _ = self.try_set_response(id, f(req));
+        }
+        count
+    }
+
+    /// Takes the pending request like `get_request()`, but tells
+    /// why there is none instead of returning `None`.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use thread_control::*;
+    /// use thread_control::interaction::Poll;
+    ///
+    /// fn main() {
+    ///     let (requester, responder) = interaction::<u32, u32>();
+    ///     assert_eq!(responder.poll(), Poll::Idle);
+    ///     let id = requester.submit(1).unwrap();
+    ///     assert_eq!(responder.poll(), Poll::Request(id, 1));
+    ///     responder.set_response(id, 2);
+    ///     assert_eq!(responder.poll(), Poll::ResponsePending);
+    ///     assert_eq!(requester.wait(id, None), Ok(2));
+    ///     assert_eq!(responder.poll(), Poll::Idle);
+    ///     drop(requester);
+    ///     assert_eq!(responder.poll(), Poll::Disconnected);
+    /// }
+    /// ```
+    pub fn poll(&self) -> Poll<I> {
+        let mut shared = match self.data.lock() {
+            Ok(shared) => shared,
+            Err(_) => return Poll::Disconnected,
+        };
+        if let Some((id, req)) = self.data.take_request(&mut shared) {
+            return Poll::Request(id, req);
+        }
+        if shared.slots.iter().any(|slot| matches!(slot.state, State::Response(_))) {
+            Poll::ResponsePending
+        } else if !self.is_connected() {
+            Poll::Disconnected
+        } else {
+            Poll::Idle
+        }
+    }
+
+    /// Takes the pending request like `get_request()`, but returns it
+    /// in a guard which must be used to respond. If the guard is dropped
+    /// without a response, e.g. by a panic of the handler, the slot is
+    /// released and the requester gets `WrongState` instead of hanging.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use std::thread;
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    ///     let (requester, responder) = interaction::<u32, u32>();
+    ///     let handle = thread::spawn(move || {
+    ///         for _ in 0..2 {
+    ///             let guard = loop {
+    ///                 if let Some(guard) = responder.take_request() {
+    ///                     break guard;
+    ///                 }
+    ///                 thread::yield_now();
+    ///             };
+    ///             if *guard.request() == 1 {
+    ///                 let resp = guard.request() + 1;
+    ///                 guard.respond(resp).unwrap();
+    ///             }
+    ///         }
+    ///         responder
+    ///     });
+    ///     assert_eq!(requester.request(1, None), Ok(2));
+    ///     assert_eq!(requester.request(2, None), Err(interaction::Error::WrongState));
+    ///     handle.join().unwrap();
+    /// }
+    /// ```
+    pub fn take_request(&self) -> Option<RequestGuard<'_, I, O>> {
+        self.get_request().map(|(id, req)| RequestGuard {
+            responder: self,
+            id,
+            req,
+            responded: false,
+        })
+    }
+
+    /// Takes the pending request like `get_request()`, but waits
+    /// up to `timeout` for a request to arrive if there is none.
+    /// Waits forever if `timeout` is `None`.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use std::thread;
+    /// use std::time::Duration;
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    ///     let (requester, responder) = interaction::<u32, ()>();
+    ///     let timeout = Some(Duration::from_millis(10));
+    ///     assert!(responder.get_request_timeout(timeout).is_none());
+    ///     thread::spawn(move || {
+    ///         thread::sleep(Duration::from_millis(50));
+    ///         requester.submit(7).unwrap();
+    ///         requester
+    ///     });
+    ///     let timeout = Some(Duration::from_secs(10));
+    ///     assert_eq!(responder.get_request_timeout(timeout).map(|(_, req)| req), Some(7));
+    /// }
+    /// ```
+    pub fn get_request_timeout(&self, timeout: Option<Duration>) -> Option<(u64, I)> {
+        let now = Instant::now();
+        let mut shared = self.data.lock().expect("interaction poisoned");
+        loop {
+            if let Some(pair) = self.data.take_request(&mut shared) {
+                return Some(pair);
+            }
+            shared = match timeout {
+                Some(timeout) => {
+                    let elapsed = now.elapsed();
+                    if elapsed >= timeout {
+                        return None;
+                    }
+                    self.data.arrived.wait_timeout(shared, timeout - elapsed)
+                        .expect("interaction poisoned").0
+                },
+                None => {
+                    self.data.arrived.wait(shared).expect("interaction poisoned")
+                },
+            };
+            self.data.release_abandoned(&mut shared);
+        }
+    }
+
+    /// Sets response to the request with `id`.
+    ///
+    /// # Panics
+    ///
+    /// This method panics, if request with `id` isn't in progress
+    /// (requester gave up waiting for it). Interactions made by
+    /// `interaction_latest()` also accept a response to replace.
+    pub fn set_response(&self, id: u64, resp: O) {
+        if self.try_set_response(id, resp).is_err() {
+            panic!("request {} is not in progress", id);
+        }
+    }
+
+    /// Sets response to the request with `id`.
+    /// Returns the response back if it can't be delivered, because
+    /// the requester gave up waiting for it.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use std::time::Duration;
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    ///     let (requester, responder) = interaction::<u32, String>();
+    ///     let id = requester.submit(1).unwrap();
+    ///     let (_, req) = responder.get_request().unwrap();
+    ///     let result = requester.wait(id, Some(Duration::from_millis(10)));
+    ///     assert_eq!(result, Err(interaction::Error::Timeout));
+    ///     drop(requester);
+    ///     let resp = format!("expensive result of {}", req);
+    ///     let resp = responder.try_set_response(id, resp).unwrap_err();
+    ///     assert_eq!(resp, "expensive result of 1");
+    /// }
+    /// ```
+    pub fn try_set_response(&self, id: u64, resp: O) -> Result<(), O> {
+        let mut shared = self.data.lock().expect("interaction poisoned");
+        let latest = shared.latest;
+        match shared.slot_mut(id) {
+            Some(slot) if matches!(slot.state, State::InProgress) => {
+                slot.state = State::Response(resp);
+                shared.record(StateKind::Response);
+                self.data.processed.fetch_add(1, Ordering::Relaxed);
+                self.data.responded.notify_all();
+                Ok(())
+            },
+            Some(slot) if latest && matches!(slot.state, State::Response(_)) => {
+                slot.state = State::Response(resp);
+                shared.record(StateKind::Response);
+                self.data.processed.fetch_add(1, Ordering::Relaxed);
+                self.data.responded.notify_all();
+                Ok(())
+            },
+            _ => {
+                Err(resp)
+            },
+        }
+    }
+
+    /// Returns the number of requests waiting to be taken.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    ///     let (requester, responder) = interaction_bounded::<u32, u32>(3);
+    ///     requester.submit(1).unwrap();
+    ///     requester.submit(2).unwrap();
+    ///     assert_eq!(responder.pending(), 2);
+    ///     let (id, req) = responder.get_request().unwrap();
+    ///     assert_eq!(responder.pending(), 1);
+    ///     responder.set_response(id, req);
+    ///     assert_eq!(responder.processed_count(), 1);
+    /// }
+    /// ```
+    pub fn pending(&self) -> usize {
+        pending(&self.data)
+    }
+
+    /// Returns the number of responses delivered by `set_response()`
+    /// and `try_set_response()`.
+    pub fn processed_count(&self) -> usize {
+        self.data.processed.load(Ordering::Relaxed)
+    }
+
+    /// Frees the slot of the oldest response which wasn't consumed
+    /// by its requester and returns the response, e.g. for logging.
+    /// Use it when requests are `Busy` because requesters submitted
+    /// them and never waited. The requester waiting for the cleared
+    /// response gets `WrongState`.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    ///     let (requester, responder) = interaction::<u32, u32>();
+    ///     // The requester never waits for the response
+    ///     requester.submit(1).unwrap();
+    ///     let (id, req) = responder.get_request().unwrap();
+    ///     responder.set_response(id, req * 10);
+    ///     assert_eq!(requester.submit(2), Err(interaction::Error::Busy));
+    ///     assert_eq!(responder.clear_stale_response(), Some(10));
+    ///     assert_eq!(responder.clear_stale_response(), None);
+    ///     let id = requester.submit(2).unwrap();
+    ///     let (_, req) = responder.get_request().unwrap();
+    ///     responder.set_response(id, req * 10);
+    ///     assert_eq!(requester.wait(id, None), Ok(20));
+    /// }
+    /// ```
+    pub fn clear_stale_response(&self) -> Option<O> {
+        let mut shared = self.data.lock().expect("interaction poisoned");
+        let slot = shared.slots.iter_mut()
+            .filter(|slot| matches!(slot.state, State::Response(_)))
+            .min_by_key(|slot| slot.id)?;
+        match mem::replace(&mut slot.state, State::Free) {
+            State::Response(resp) => {
+                shared.record(StateKind::Free);
+                self.data.responded.notify_all();
+                Some(resp)
+            },
+            _ => unreachable!("slot was checked to hold a response"),
+        }
+    }
+
+    /// Returns `true` while at least one requester exists.
+    pub fn is_connected(&self) -> bool {
+        self.requester_count() > 0
+    }
+
+    /// Returns the last transitions of request slots, oldest first.
+    /// Only interactions made by `interaction_debug()` record them,
+    /// others return an empty history.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use thread_control::*;
+    /// use thread_control::interaction::StateKind;
+    ///
+    /// fn main() {
+    ///     let (requester, responder) = interaction_debug::<u32, u32>(1, 3);
+    ///     let id = requester.submit(1).unwrap();
+    ///     let (_, req) = responder.get_request().unwrap();
+    ///     responder.set_response(id, req + 1);
+    ///     assert_eq!(requester.wait(id, None), Ok(2));
+    ///     // The first transition was pushed out
+    ///     let kinds: Vec<_> = responder.history().into_iter().map(|(kind, _)| kind).collect();
+    ///     assert_eq!(kinds, vec![StateKind::InProgress, StateKind::Response, StateKind::Free]);
+    ///
+    ///     let (requester, responder) = interaction::<u32, u32>();
+    ///     requester.submit(1).unwrap();
+    ///     assert!(responder.history().is_empty());
+    /// }
+    /// ```
+    pub fn history(&self) -> Vec<(StateKind, Instant)> {
+        let shared = self.data.lock().expect("interaction poisoned");
+        shared.history.iter().cloned().collect()
+    }
+
+    /// Returns the number of existing requesters including clones.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    ///     let (requester, responder) = interaction::<(), ()>();
+    ///     let clones: Vec<_> = (0..3).map(|_| requester.clone()).collect();
+    ///     assert_eq!(responder.requester_count(), 4);
+    ///     drop(requester);
+    ///     assert_eq!(responder.requester_count(), 3);
+    ///     drop(clones);
+    ///     assert_eq!(responder.requester_count(), 0);
+    ///     assert_eq!(responder.is_connected(), false);
+    /// }
+    /// ```
+    pub fn requester_count(&self) -> usize {
+        self.requesters.load(Ordering::Acquire)
+    }
+
+    /// Handles requests with `handler` until all requesters are dropped.
+    /// Responses to requests which requesters gave up are discarded.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use std::thread;
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    ///     let (requester, responder) = interaction::<u32, u32>();
+    ///     let handle = thread::spawn(move || {
+    ///         let mut handled = 0;
+    ///         responder.run(|req| {
+    ///             handled += 1;
+    ///             req * 2
+    ///         });
+    ///         handled
+    ///     });
+    ///     assert_eq!(requester.request(1, None), Ok(2));
+    ///     assert_eq!(requester.request(2, None), Ok(4));
+    ///     drop(requester);
+    ///     assert_eq!(handle.join().unwrap(), 2);
+    /// }
+    /// ```
+    pub fn run<F>(&self, mut handler: F)
+        where F: FnMut(I) -> O
+    {
+        let mut shared = self.data.lock().expect("interaction poisoned");
+        // Checked under the lock, the last requester notifies after taking it
+        while self.is_connected() {
+            match self.data.take_request(&mut shared) {
+                Some((id, req)) => {
+                    drop(shared);
+                    let _ = self.try_set_response(id, handler(req));
+                    shared = self.data.lock().expect("interaction poisoned");
+                },
+                None => {
+                    // Blocks until a request arrives or the last requester is dropped
+                    shared = self.data.arrived.wait(shared).expect("interaction poisoned");
+                    self.data.release_abandoned(&mut shared);
+                },
+            }
+        }
+    }
+
+    /// Spawns thread which handles requests with `handler` until
+    /// it's stopped by the returned `Control`.
+    /// Responses to requests which requesters gave up are discarded.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    ///     let (requester, responder) = interaction::<u32, u32>();
+    ///     let control = responder.spawn_loop(|req| req * 3);
+    ///     assert_eq!(requester.request(1, None), Ok(3));
+    ///     assert_eq!(requester.request(2, None), Ok(6));
+    ///     control.stop();
+    ///     assert!(control.join_result().is_ok());
+    ///     assert_eq!(control.status(), Status::Stopped);
+    /// }
+    /// ```
+    pub fn spawn_loop<F>(self, mut handler: F) -> Control
+        where F: FnMut(I) -> O + Send + 'static,
+              I: Send + 'static,
+              O: Send + 'static,
+    {
+        spawn(move |flag| {
+            while flag.alive() {
+                // Wakes up regularly to notice the stop
+                if let Some((id, req)) = self.get_request_timeout(Some(LOOP_TICK)) {
+                    let _ = self.try_set_response(id, handler(req));
+                }
+            }
+        })
+    }
+
+    /// Spawns thread which handles requests with `handler` like
+    /// `spawn_loop()` and returns `Service` which controls the thread
+    /// and reports metrics of the interaction.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    ///     let (requester, responder) = interaction::<u32, u32>();
+    ///     let service = responder.spawn_service(|req| req + 1);
+    ///     for req in 0..3 {
+    ///         assert_eq!(requester.request(req, None), Ok(req + 1));
+    ///     }
+    ///     assert_eq!(service.pending(), 0);
+    ///     assert_eq!(service.processed(), 3);
+    ///     service.stop();
+    ///     assert!(service.control().join_result().is_ok());
+    ///     assert_eq!(service.control().status(), Status::Stopped);
+    ///     // The interaction is closed by the stop
+    ///     assert_eq!(requester.request(4, None), Err(interaction::Error::Closed));
+    ///     assert_eq!(service.processed(), 3);
+    /// }
+    /// ```
+    pub fn spawn_service<F>(self, handler: F) -> Service<I, O>
+        where F: FnMut(I) -> O + Send + 'static,
+              I: Send + 'static,
+              O: Send + 'static,
+    {
+        let data = self.data.clone();
+        let control = self.spawn_loop(handler);
+        Service {
+            control,
+            data,
+        }
+    }
+
+    /// Returns blocking iterator over incoming requests which ends
+    /// when `flag` is stopped by its `Control`.
+    ///
+    /// # Panics
+    ///
+    /// The iterator panics, if interrupt flag was set.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use std::thread;
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    ///     let (requester, responder) = interaction::<u32, u32>();
+    ///     let (flag, control) = make_pair();
+    ///     let handle = thread::spawn(move || {
+    ///         for (id, req) in responder.requests(&flag) {
+    ///             responder.set_response(id, req + 1);
+    ///         }
+    ///     });
+    ///     assert_eq!(requester.request(1, None), Ok(2));
+    ///     control.stop();
+    ///     handle.join().unwrap();
+    ///     assert_eq!(control.is_done(), true);
+    /// }
+    /// ```
+    pub fn requests<'a>(&'a self, flag: &'a Flag) -> Requests<'a, I, O> {
+        Requests {
+            responder: self,
+            flag,
+        }
+    }
+
+    /// Makes responder which converts incoming requests with `f`
+    /// before they are returned by `get_request()`.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use thread_control::*;
+    ///
+    /// #[derive(Debug, PartialEq)]
+    /// enum Command {
+    ///     Start,
+    ///     Stop,
+    ///     Unknown,
+    /// }
+    ///
+    /// fn main() {
+    ///     let (requester, responder) = interaction::<Vec<u8>, bool>();
+    ///     let responder = responder.map_request(|raw: Vec<u8>| {
+    ///         match raw.as_slice() {
+    ///             b"start" => Command::Start,
+    ///             b"stop" => Command::Stop,
+    ///             _ => Command::Unknown,
+    ///         }
+    ///     });
+    ///     let id = requester.submit(b"stop".to_vec()).unwrap();
+    ///     let (_, command) = responder.get_request().unwrap();
+    ///     assert_eq!(command, Command::Stop);
+    ///     responder.set_response(id, true);
+    ///     assert_eq!(requester.wait(id, None), Ok(true));
+    /// }
+    /// ```
+    pub fn map_request<I2, F>(self, f: F) -> MappedResponder<I, I2, O, F>
+        where F: Fn(I) -> I2
+    {
+        MappedResponder {
+            responder: self,
+            f,
+            _request: PhantomData,
+        }
+    }
+}
+
+/// Responder thread with its control, made by `Responder::spawn_service()`.
+pub struct Service<I, O> {
+    control: Control,
+    data: Arc<Inner<I, O>>,
+}
+
+impl<I, O> fmt::Debug for Service<I, O> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Service").field("control", &self.control).finish()
+    }
+}
+
+impl<I, O> Service<I, O> {
+    /// Returns the number of requests waiting to be taken.
+    pub fn pending(&self) -> usize {
+        pending(&self.data)
+    }
+
+    /// Returns the number of responses delivered by the thread.
+    pub fn processed(&self) -> usize {
+        self.data.processed.load(Ordering::Relaxed)
+    }
+
+    /// Stops the thread gracefully and closes the interaction.
+    /// Waiting requesters get `Error::Closed` at once instead of
+    /// waiting for the timeout, and so do new requests.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use std::sync::mpsc;
+    /// use std::thread;
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    ///     let (requester, responder) = interaction::<u32, u32>();
+    ///     let (taken, waiting) = mpsc::channel();
+    ///     let service = responder.spawn_service(move |req| {
+    ///         taken.send(()).unwrap();
+    ///         // Never answers in time
+    ///         thread::park();
+    ///         req
+    ///     });
+    ///     let handle = thread::spawn(move || requester.request(1, None));
+    ///     waiting.recv().unwrap();
+    ///     service.stop();
+    ///     assert_eq!(handle.join().unwrap(), Err(interaction::Error::Closed));
+    /// }
+    /// ```
+    pub fn stop(&self) {
+        self.data.closed.store(true, Ordering::Release);
+        // Taken to not notify between the check and the wait of a requester
+        drop(self.data.shared.lock());
+        self.data.responded.notify_all();
+        self.control.stop()
+    }
+
+    /// Returns the control of the thread.
+    pub fn control(&self) -> &Control {
+        &self.control
+    }
+}
+
+fn pending<I, O>(data: &Inner<I, O>) -> usize {
+    let shared = data.lock().expect("interaction poisoned");
+    shared.slots.iter()
+        .filter(|slot| matches!(slot.state, State::Request(_)))
+        .count()
+}
+
+/// Request in progress, made by `Responder::take_request()`.
+/// Releases the slot of the request if it's dropped without a response.
+pub struct RequestGuard<'a, I: 'a, O: 'a> {
+    responder: &'a Responder<I, O>,
+    id: u64,
+    req: I,
+    responded: bool,
+}
+
+impl<'a, I, O> fmt::Debug for RequestGuard<'a, I, O> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RequestGuard").field("id", &self.id).finish()
+    }
+}
+
+impl<'a, I, O> RequestGuard<'a, I, O> {
+    /// Returns the correlation id of the request.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Returns the request.
+    pub fn request(&self) -> &I {
+        &self.req
+    }
+
+    /// Returns the request to modify it in place.
+    pub fn request_mut(&mut self) -> &mut I {
+        &mut self.req
+    }
+
+    /// Sets response to the request like `Responder::try_set_response()`.
+    pub fn respond(mut self, resp: O) -> Result<(), O> {
+        self.responded = true;
+        self.responder.try_set_response(self.id, resp)
+    }
+}
+
+impl<'a, I, O> Drop for RequestGuard<'a, I, O> {
+    fn drop(&mut self) {
+        if self.responded {
+            return;
+        }
+        // Poisoned by a panic of the other side, nobody waits anymore
+        if let Ok(mut shared) = self.responder.data.lock() {
+            if let Some(slot) = shared.slot_mut(self.id) {
+                if matches!(slot.state, State::InProgress) {
+                    slot.state = State::Free;
+                    shared.record(StateKind::Free);
+                    self.responder.data.responded.notify_all();
+                }
+            }
+        }
+    }
+}
+
+/// Blocking iterator over requests, made by `Responder::requests()`.
+pub struct Requests<'a, I: 'a, O: 'a> {
+    responder: &'a Responder<I, O>,
+    flag: &'a Flag,
+}
+
+impl<'a, I, O> fmt::Debug for Requests<'a, I, O> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Requests").finish()
+    }
+}
+
+impl<'a, I, O> Iterator for Requests<'a, I, O> {
+    type Item = (u64, I);
+
+    fn next(&mut self) -> Option<(u64, I)> {
+        while self.flag.alive() {
+            // Blocks between requests, but wakes up regularly to check the flag
+            if let Some(pair) = self.responder.get_request_timeout(Some(LOOP_TICK)) {
+                return Some(pair);
+            }
+        }
+        None
+    }
+}
+
+/// Responder which converts requests, made by `Responder::map_request()`.
+/// Responses are set unchanged.
+pub struct MappedResponder<I, I2, O, F> {
+    responder: Responder<I, O>,
+    f: F,
+    _request: PhantomData<fn() -> I2>,
+}
+
+impl<I, I2, O, F> fmt::Debug for MappedResponder<I, I2, O, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MappedResponder").finish()
+    }
+}
+
+impl<I, I2, O, F> MappedResponder<I, I2, O, F>
+    where F: Fn(I) -> I2
+{
+    /// Takes the oldest pending request and converts it.
+    /// Returns `None` if there is no pending request.
+    pub fn get_request(&self) -> Option<(u64, I2)> {
+        let f = &self.f;
+        self.responder.get_request().map(|(id, req)| (id, f(req)))
+    }
+
+    /// Sets response to the request with `id`.
+    /// See `Responder::set_response()`.
+    pub fn set_response(&self, id: u64, resp: O) {
+        self.responder.set_response(id, resp)
+    }
+
+    /// Sets response to the request with `id`.
+    /// See `Responder::try_set_response()`.
+    pub fn try_set_response(&self, id: u64, resp: O) -> Result<(), O> {
+        self.responder.try_set_response(id, resp)
+    }
+
+    /// Returns the original responder.
+    pub fn into_inner(self) -> Responder<I, O> {
+        self.responder
+    }
+}
+
+// Responses of a single request of a stream interaction
+struct Stream<O> {
+    items: VecDeque<O>,
+    ended: bool,
+}
+
+/// Side of stream interaction which sends requests,
+/// made by `interaction_stream()`.
+pub struct StreamRequester<I, O> {
+    requester: Requester<I, Stream<O>>,
+}
+
+impl<I, O> Clone for StreamRequester<I, O> {
+    fn clone(&self) -> Self {
+        StreamRequester {
+            requester: self.requester.clone(),
+        }
+    }
+}
+
+impl<I, O> fmt::Debug for StreamRequester<I, O> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("StreamRequester").finish()
+    }
+}
+
+impl<I, O> StreamRequester<I, O> {
+    /// Sends request and returns blocking iterator over its responses.
+    /// The iterator ends when the responder ends the response or is dropped.
+    pub fn request_stream(&self, req: I) -> Result<ResponseStream<I, O>, Error> {
+        let id = self.requester.submit(req)?;
+        Ok(ResponseStream {
+            data: self.requester.data.clone(),
+            id,
+            done: false,
+        })
+    }
+
+    /// Sends request and waits up to `timeout` for the end of its
+    /// responses like `request()`, the last response is the result.
+    /// Earlier responses are partial results, the latest of them is
+    /// returned with `Timeout` if the timeout expires, and the request
+    /// is given up. Fails with `ThreadDead` if the responder is dropped
+    /// or ends the response without any.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use std::sync::mpsc;
+    /// use std::thread;
+    /// use std::time::Duration;
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    ///     let (requester, responder) = interaction_stream::<u32, u32>();
+    ///     let (release, stalled) = mpsc::channel();
+    ///     thread::spawn(move || {
+    ///         loop {
+    ///             if let Some((id, req)) = responder.get_request() {
+    ///                 responder.push_response(id, req * 5).unwrap();
+    ///                 if req == 1 {
+    ///                     // Stalls after the first partial result until released
+    ///                     stalled.recv().unwrap();
+    ///                 }
+    ///                 let _ = responder.push_response(id, req * 10);
+    ///                 responder.end_response(id);
+    ///             }
+    ///             thread::yield_now();
+    ///         }
+    ///     });
+    ///     let timeout = Some(Duration::from_secs(1));
+    ///     let result = requester.request_or_partial(1, timeout);
+    ///     assert_eq!(result, Err((interaction::Error::Timeout, Some(5))));
+    ///     release.send(()).unwrap();
+    ///     let timeout = Some(Duration::from_secs(30));
+    ///     assert_eq!(requester.request_or_partial(2, timeout), Ok(20));
+    /// }
+    /// ```
+    pub fn request_or_partial(&self, req: I, timeout: Option<Duration>) -> Result<O, (Error, Option<O>)> {
+        let now = Instant::now();
+        let mut stream = self.request_stream(req).map_err(|err| (err, None))?;
+        let mut last = None;
+        loop {
+            match stream.poll(timeout.map(|timeout| timeout.saturating_sub(now.elapsed()))) {
+                Some(Some(item)) => {
+                    last = Some(item);
+                },
+                Some(None) => {
+                    stream.done = true;
+                    return last.ok_or((Error::ThreadDead, None));
+                },
+                None => {
+                    // Dropping the stream gives up the request
+                    return Err((Error::Timeout, last));
+                },
+            }
+        }
+    }
+}
+
+/// Blocking iterator over responses to one request,
+/// made by `StreamRequester::request_stream()`.
+/// Dropping it before the end gives up the request.
+pub struct ResponseStream<I, O> {
+    data: Weak<Inner<I, Stream<O>>>,
+    id: u64,
+    done: bool,
+}
+
+impl<I, O> fmt::Debug for ResponseStream<I, O> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ResponseStream").field("id", &self.id).finish()
+    }
+}
+
+impl<I, O> ResponseStream<I, O> {
+    // Waits up to `timeout` for the next response, forever if it's `None`.
+    // Returns `None` if the timeout expired and `Some(None)` at the end.
+    fn poll(&self, timeout: Option<Duration>) -> Option<Option<O>> {
+        let now = Instant::now();
+        let data = match self.data.upgrade() {
+            Some(data) => data,
+            None => return Some(None),
+        };
+        let mut shared = match data.lock() {
+            Ok(shared) => shared,
+            Err(_) => return Some(None),
+        };
+        loop {
+            let slot = match shared.slot_mut(self.id) {
+                Some(slot) => slot,
+                None => return Some(None),
+            };
+            let ended = match slot.state {
+                State::Response(ref mut stream) => {
+                    if let Some(item) = stream.items.pop_front() {
+                        return Some(Some(item));
+                    }
+                    stream.ended
+                },
+                State::Request(_) | State::InProgress => {
+                    false
+                },
+                State::Free | State::Ping | State::Pong => {
+                    return Some(None);
+                },
+            };
+            if ended {
+                slot.state = State::Free;
+                shared.record(StateKind::Free);
+                return Some(None);
+            }
+            // Checked under the lock, the responder notifies after taking it
+            if !data.responder_alive.load(Ordering::Acquire) {
+                return Some(None);
+            }
+            // Poisoned by a panic of the responder, it's dead
+            let waited = match timeout {
+                Some(timeout) => {
+                    let elapsed = now.elapsed();
+                    if elapsed >= timeout {
+                        return None;
+                    }
+                    data.responded.wait_timeout(shared, timeout - elapsed).ok().map(|(shared, _)| shared)
+                },
+                None => {
+                    data.responded.wait(shared).ok()
+                },
+            };
+            shared = match waited {
+                Some(shared) => shared,
+                None => return Some(None),
+            };
+            data.release_abandoned(&mut shared);
+        }
+    }
+}
+
+impl<I, O> Iterator for ResponseStream<I, O> {
+    type Item = O;
+
+    fn next(&mut self) -> Option<O> {
+        if self.done {
+            return None;
+        }
+        // Blocks until the next response or the end
+        match self.poll(None) {
+            Some(Some(item)) => {
+                Some(item)
+            },
+            _ => {
+                self.done = true;
+                None
+            },
+        }
+    }
+}
+
+impl<I, O> Drop for ResponseStream<I, O> {
+    fn drop(&mut self) {
+        if !self.done {
+            if let Some(data) = self.data.upgrade() {
+                data.abandon(self.id);
+            }
+        }
+    }
+}
+
+/// Side of stream interaction which handles requests,
+/// made by `interaction_stream()`.
+pub struct StreamResponder<I, O> {
+    responder: Responder<I, Stream<O>>,
+}
+
+impl<I, O> fmt::Debug for StreamResponder<I, O> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("StreamResponder").finish()
+    }
+}
+
+impl<I, O> StreamResponder<I, O> {
+    /// Takes the oldest pending request together with its correlation id.
+    /// Returns `None` if there is no pending request.
+    pub fn get_request(&self) -> Option<(u64, I)> {
+        self.responder.get_request()
+    }
+
+    /// Appends response to the request with `id`.
+    /// Returns the response back if the request isn't in progress
+    /// (requester gave up waiting for it) or its response was ended.
+    pub fn push_response(&self, id: u64, resp: O) -> Result<(), O> {
+        let mut shared = self.responder.data.lock().expect("interaction poisoned");
+        let pushed = match shared.slot_mut(id) {
+            Some(slot) => match slot.state {
+                State::InProgress => {
+                    let mut items = VecDeque::new();
+                    items.push_back(resp);
+                    slot.state = State::Response(Stream { items, ended: false });
+                    shared.record(StateKind::Response);
+                    Ok(())
+                },
+                State::Response(ref mut stream) if !stream.ended => {
+                    stream.items.push_back(resp);
+                    Ok(())
+                },
+                _ => {
+                    Err(resp)
+                },
+            },
+            None => {
+                Err(resp)
+            },
+        };
+        if pushed.is_ok() {
+            self.responder.data.responded.notify_all();
+        }
+        pushed
+    }
+
+    /// Ends response to the request with `id`. The requester gets
+    /// the rest of already pushed responses and then the stream ends.
+    /// Returns `false` if the request isn't in progress.
+    pub fn end_response(&self, id: u64) -> bool {
+        let mut shared = self.responder.data.lock().expect("interaction poisoned");
+        let ended = match shared.slot_mut(id) {
+            Some(slot) => match slot.state {
+                State::InProgress => {
+                    slot.state = State::Response(Stream { items: VecDeque::new(), ended: true });
+                    shared.record(StateKind::Response);
+                    true
+                },
+                State::Response(ref mut stream) => {
+                    stream.ended = true;
+                    true
+                },
+                _ => {
+                    false
+                },
+            },
+            None => {
+                false
+            },
+        };
+        if ended {
+            self.responder.data.responded.notify_all();
+        }
+        ended
+    }
+
+    /// Returns `true` while at least one requester exists.
+    pub fn is_connected(&self) -> bool {
+        self.responder.is_connected()
+    }
+}
+
+/// Makes interaction where the responder answers a request with
+/// a stream of responses, e.g. progress updates.
+///
+/// Example:
+///
+/// ```rust
+/// use std::thread;
+/// use thread_control::*;
+///
+/// fn main() {
+///     let (requester, responder) = interaction_stream::<u32, String>();
+///     let handle = thread::spawn(move || {
+///         let (id, req) = loop {
+///             if let Some(pair) = responder.get_request() {
+///                 break pair;
+///             }
+///             thread::yield_now();
+///         };
+///         for step in 1..4 {
+///             responder.push_response(id, format!("{}: step {}", req, step)).unwrap();
+///         }
+///         assert!(responder.end_response(id));
+///         responder
+///     });
+///     let responses: Vec<_> = requester.request_stream(7).unwrap().collect();
+///     assert_eq!(responses, vec!["7: step 1", "7: step 2", "7: step 3"]);
+///     handle.join().unwrap();
+/// }
+/// ```
+pub fn interaction_stream<I, O>() -> (StreamRequester<I, O>, StreamResponder<I, O>) {
+    let (requester, responder) = interaction();
+    (StreamRequester { requester }, StreamResponder { responder })
+}
+
+/// Makes interaction with a single request slot.
+pub fn interaction<I, O>() -> (Requester<I, O>, Responder<I, O>) {
+    interaction_bounded(1)
+}
+
+/// Makes interaction where setting a response replaces the previous one
+/// if it wasn't consumed yet, so the requester gets the freshest value.
+///
+/// Example:
+///
+/// ```rust
+/// use thread_control::*;
+///
+/// fn main() {
+///     let (requester, responder) = interaction_latest::<(), u32>();
+///     requester.submit(()).unwrap();
+///     let (id, _) = responder.get_request().unwrap();
+///     responder.set_response(id, 1);
+///     responder.set_response(id, 2);
+///     responder.set_response(id, 3);
+///     assert_eq!(requester.wait(id, None), Ok(3));
+/// }
+/// ```
+pub fn interaction_latest<I, O>() -> (Requester<I, O>, Responder<I, O>) {
+    make(1, true, 0)
+}
+
+/// Makes interaction like `interaction_bounded()` which records the last
+/// `history` transitions of its slots with timestamps. Read them with
+/// `Responder::history()` to find out how a deadlock or `WrongState`
+/// happened.
+///
+/// # Panics
+///
+/// This function panics, if `capacity` is `0`.
+///
+/// Example:
+///
+/// ```rust
+/// use std::thread;
+/// use thread_control::*;
+/// use thread_control::interaction::StateKind;
+///
+/// fn main() {
+///     let (requester, responder) = interaction_debug::<u32, u32>(1, 16);
+///     let handle = thread::spawn(move || {
+///         responder.run(|req| req * 2);
+///         responder
+///     });
+///     assert_eq!(requester.request(1, None), Ok(2));
+///     assert!(requester.ping(None).is_ok());
+///     drop(requester);
+///     let responder = handle.join().unwrap();
+///     let history = responder.history();
+///     let kinds: Vec<_> = history.iter().map(|&(kind, _)| kind).collect();
+///     assert_eq!(kinds, vec![
+///         StateKind::Request,
+///         StateKind::InProgress,
+///         StateKind::Response,
+///         StateKind::Free,
+///         StateKind::Ping,
+///         StateKind::Pong,
+///         StateKind::Free,
+///     ]);
+///     assert!(history.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+/// }
+/// ```
+pub fn interaction_debug<I, O>(capacity: usize, history: usize) -> (Requester<I, O>, Responder<I, O>) {
+    make(capacity, false, history)
+}
+
+/// Requester of interaction made by `interaction_boxed()`.
+pub type BoxedRequester<I, O> = Requester<Box<I>, Box<O>>;
+
+/// Responder of interaction made by `interaction_boxed()`.
+pub type BoxedResponder<I, O> = Responder<Box<I>, Box<O>>;
+
+/// Makes interaction which moves requests and responses boxed.
+///
+/// Only pointers are moved while the lock is held, so it reduces
+/// lock hold time when `I` or `O` are large.
+///
+/// Example:
+///
+/// ```rust
+/// use thread_control::*;
+///
+/// fn main() {
+///     let (requester, responder) = interaction_boxed::<[u8; 4096], usize>();
+///     let id = requester.submit(Box::new([1; 4096])).unwrap();
+///     let (_, req) = responder.get_request().unwrap();
+///     responder.set_response(id, Box::new(req.iter().map(|b| *b as usize).sum()));
+///     assert_eq!(requester.wait(id, None), Ok(Box::new(4096)));
+/// }
+/// ```
+pub fn interaction_boxed<I, O>() -> (BoxedRequester<I, O>, BoxedResponder<I, O>) {
+    interaction()
+}
+
+/// Makes interaction with a single request slot whose requester waits
+/// up to `default` in `Requester::request_default()`.
+///
+/// Example:
+///
+/// ```rust
+/// use std::thread;
+/// use std::time::Duration;
+/// use thread_control::*;
+///
+/// fn main() {
+///     let default = Duration::from_millis(20);
+///     let (requester, _responder) = interaction_with_timeout::<u32, u32>(default);
+///     // Nobody answers
+///     assert_eq!(requester.request_default(1), Err(interaction::Error::Timeout));
+///
+///     let (requester, responder) = interaction_with_timeout::<u32, u32>(default);
+///     let control = responder.spawn_loop(|req| {
+///         thread::sleep(Duration::from_millis(100));
+///         req * 2
+///     });
+///     assert_eq!(requester.request_default(1), Err(interaction::Error::Timeout));
+///     // The override waits longer than the default
+///     let timeout = Some(Duration::from_secs(30));
+///     assert_eq!(requester.request(2, timeout), Ok(4));
+///     control.stop();
+/// }
+/// ```
+pub fn interaction_with_timeout<I, O>(default: Duration) -> (Requester<I, O>, Responder<I, O>) {
+    let (mut requester, responder) = interaction();
+    requester.default_timeout = Some(default);
+    (requester, responder)
+}
+
+/// Makes interaction which holds up to `capacity` requests at once.
+///
+/// # Panics
+///
+/// This function panics, if `capacity` is `0`.
+pub fn interaction_bounded<I, O>(capacity: usize) -> (Requester<I, O>, Responder<I, O>) {
+    make(capacity, false, 0)
+}
+
+fn make<I, O>(capacity: usize, latest: bool, history_len: usize) -> (Requester<I, O>, Responder<I, O>) {
+    assert!(capacity > 0, "interaction capacity must be positive");
+    let slots = (0..capacity).map(|_| Slot { id: 0, state: State::Free }).collect();
+    let shared = Shared {
+        next_id: 1,
+        slots,
+        pending: BinaryHeap::new(),
+        latest,
+        coalesced: Vec::new(),
+        history: VecDeque::with_capacity(history_len),
+        history_len,
+    };
+    let data = Arc::new(Inner {
+        shared: Mutex::new(shared),
+        abandoned: Mutex::new(Vec::new()),
+        arrived: Condvar::new(),
+        responded: Condvar::new(),
+        responder_alive: AtomicBool::new(true),
+        responders: AtomicUsize::new(1),
+        closed: AtomicBool::new(false),
+        processed: AtomicUsize::new(0),
+    });
+    let requesters = Arc::new(AtomicUsize::new(1));
+    let requester = Requester {
+        data: Arc::downgrade(&data),
+        requesters: requesters.clone(),
+        spins: DEFAULT_SPINS,
+        default_timeout: None,
+    };
+    let responder = Responder {
+        data,
+        requesters,
+    };
+    (requester, responder)
+}
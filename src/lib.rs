@@ -36,6 +36,7 @@
 //!     handle.join();
 //!     assert_eq!(control.is_interrupted(), true);
 //!     assert_eq!(control.is_done(), true);
+//!     assert_eq!(control.status(), Status::Interrupted);
 //! }
 //! ```
 //!
@@ -59,103 +60,3227 @@
 //! ```
 //!
 
+// Only signal handling of the `unix` feature and eventfd of the `linux`
+// feature need unsafe code, the default build is free of it
+#![cfg_attr(not(any(feature = "unix", feature = "linux")), forbid(unsafe_code))]
+#![cfg_attr(any(feature = "unix", feature = "linux"), deny(unsafe_code))]
+
+#[cfg(feature = "tracing")]
+#[macro_use]
+extern crate tracing;
+#[cfg(feature = "metrics")]
+#[macro_use]
+extern crate metrics;
+#[cfg(feature = "futures")]
+extern crate futures;
+#[cfg(feature = "tokio")]
+extern crate tokio;
+#[cfg(feature = "rayon")]
+extern crate rayon;
+#[cfg(any(all(unix, feature = "unix"), all(target_os = "linux", feature = "linux")))]
+extern crate libc;
+
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::cell::{Cell, RefCell};
+use std::cmp;
+use std::error;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io;
 use std::thread;
-use std::sync::{Arc, Weak};
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::ops::ControlFlow;
+use std::panic::{self, Location};
+use std::sync::{mpsc, Arc, Mutex, OnceLock, Weak};
+use std::sync::atomic::{fence, AtomicBool, AtomicI64, AtomicU8, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+#[cfg(feature = "futures")]
+use std::panic::AssertUnwindSafe;
+#[cfg(feature = "futures")]
+use futures::task::AtomicWaker;
+#[cfg(feature = "tokio")]
+use tokio::sync::{watch, Notify};
+
+pub mod interaction;
+pub mod pool;
+pub mod shutdown;
+
+/// Counters of the `metrics` feature.
+///
+/// Example:
+///
+/// ```rust
+/// extern crate metrics;
+/// extern crate metrics_util;
+/// extern crate thread_control;
+///
+/// use std::panic;
+/// use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+/// use thread_control::*;
+///
+/// fn main() {
+///     let recorder = DebuggingRecorder::new();
+///     let snapshotter = recorder.snapshotter();
+///     metrics::with_local_recorder(&recorder, || {
+///         let (_flag, control) = make_pair();
+///         control.stop();
+///         let (flag, control) = make_pair();
+///         control.interrupt();
+///         let _ = panic::catch_unwind(move || {
+///             flag.alive();
+///         });
+///         let (requester, _responder) = interaction::<u32, u32>();
+///         requester.submit(1).unwrap();
+///         let (_flag, control) = Control::builder().name("indexer").build();
+///         control.stop();
+///     });
+///     let mut counters: Vec<_> = snapshotter.snapshot().into_vec().into_iter()
+///         .map(|(key, _, _, value)| {
+///             let name = key.key().labels().map(|label| label.value().to_owned()).next();
+///             (key.key().name().to_owned(), name, value)
+///         })
+///         .collect();
+///     counters.sort_by(|a, b| (&a.0, &a.1).cmp(&(&b.0, &b.1)));
+///     assert_eq!(counters, vec![
+///         ("thread_control.interrupts".to_owned(), None, DebugValue::Counter(1)),
+///         ("thread_control.panics".to_owned(), None, DebugValue::Counter(1)),
+///         ("thread_control.requests".to_owned(), None, DebugValue::Counter(1)),
+///         ("thread_control.stops".to_owned(), None, DebugValue::Counter(1)),
+///         ("thread_control.stops".to_owned(), Some("indexer".to_owned()), DebugValue::Counter(1)),
+///     ]);
+/// }
+/// ```
+#[cfg(feature = "metrics")]
+pub mod counters;
+#[cfg(not(feature = "metrics"))]
+mod counters;
+#[cfg(feature = "futures")]
+pub mod future;
+#[cfg(feature = "tokio")]
+pub mod cancel;
+#[cfg(all(unix, feature = "unix"))]
+#[allow(unsafe_code)]
+pub mod signal;
+#[cfg(all(target_os = "linux", feature = "linux"))]
+#[allow(unsafe_code)]
+pub mod eventfd;
+#[cfg(feature = "test-support")]
+pub mod test_support;
+#[cfg(feature = "registry")]
+pub mod registry;
+#[cfg(feature = "rayon")]
+pub mod parallel;
+
+pub use interaction::{interaction, interaction_bounded, interaction_boxed, interaction_latest};
+pub use interaction::{interaction_debug, interaction_stream, interaction_with_timeout};
+pub use interaction::{Requester, Responder, Service};
+pub use pool::FlagPool;
+pub use shutdown::ShutdownPlan;
+
+static GLOBAL_STOP: AtomicBool = AtomicBool::new(false);
+
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+// Controls stopped by `ProcessExitGuard`
+static EXIT_CONTROLS: Mutex<Vec<Control>> = Mutex::new(Vec::new());
+
+/// Bit of `Flag::state_bits()` which is set while the flag isn't stopped.
+pub const STATE_ALIVE: u8 = 0b001;
+/// Bit of `Flag::state_bits()` which is set when the flag is interrupted.
+pub const STATE_INTERRUPTED: u8 = 0b010;
+/// Bit of `Flag::state_bits()` which is set while the worker is paused.
+pub const STATE_PAUSED: u8 = 0b100;
+
+// Most recent `Flag::record()` calls kept for `Control::throughput()`
+const WORK_LOG_LEN: usize = 1024;
+
+/// Stops all flags of the process at once.
+///
+/// It's a process-wide switch: every `Flag` checks it in `alive()`
+/// and `is_alive()`, regardless of the pair it belongs to. It's
+/// intended for test harnesses which have to halt all controlled
+/// threads (e.g. on a failure). Flags stay stopped, including flags
+/// created later, until `global_reset()` is called.
+///
+/// Example:
+///
+/// ```rust
+/// use std::thread;
+/// use thread_control::*;
+///
+/// fn main() {
+///     let handles: Vec<_> = (0..3).map(|_| {
+///         let (flag, control) = make_pair();
+///         let handle = thread::spawn(move || {
+///             while flag.alive() {
+///             }
+///         });
+///         (handle, control)
+///     }).collect();
+///     global_stop();
+///     for (handle, control) in handles {
+///         handle.join().unwrap();
+///         assert_eq!(control.is_done(), true);
+///         assert_eq!(control.is_interrupted(), false);
+///     }
+///     global_reset();
+///     assert_eq!(Flag::new().is_alive(), true);
+/// }
+/// ```
+pub fn global_stop() {
+    GLOBAL_STOP.store(true, Ordering::Relaxed)
+}
+
+/// Clears the switch set by `global_stop()`.
+///
+/// Since the switch is shared by the whole process, tests running
+/// in parallel threads will observe each other's calls.
+pub fn global_reset() {
+    GLOBAL_STOP.store(false, Ordering::Relaxed)
+}
+
+/// Execution status of a thread.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Status {
+    /// The flag is still alive.
+    Running,
+    /// The flag was dropped without stop or interrupt.
+    Finished,
+    /// The flag was dropped after stop.
+    Stopped,
+    /// The flag was dropped after interrupt or panic.
+    Interrupted,
+    /// The flag was dropped before `Flag::mark_started()`,
+    /// see `ControlBuilder::track_start()`.
+    NeverStarted,
+}
+
+impl Status {
+    /// Return `true` if the status can't change anymore.
+    pub fn is_terminal(&self) -> bool {
+        *self != Status::Running
+    }
+
+    fn from_u8(value: u8) -> Self {
+        match value {
+            0 => Status::Running,
+            1 => Status::Finished,
+            2 => Status::Stopped,
+            3 => Status::Interrupted,
+            4 => Status::NeverStarted,
+            _ => unreachable!("unknown status {}", value),
+        }
+    }
+
+    fn to_u8(self) -> u8 {
+        match self {
+            Status::Running => 0,
+            Status::Finished => 1,
+            Status::Stopped => 2,
+            Status::Interrupted => 3,
+            Status::NeverStarted => 4,
+        }
+    }
+}
+
+/// Payload of the panic raised by `Flag::alive()` on interrupt.
+/// Downcast the payload caught by `catch_unwind()` to inspect it.
+/// The default panic hook can't print it, a custom one can use `Display`.
+///
+/// Example:
+///
+/// ```rust
+/// use std::panic;
+/// use thread_control::*;
+///
+/// fn main() {
+///     let (flag, control) = Control::builder().name("indexer").build();
+///     control.interrupt();
+///     let payload = panic::catch_unwind(move || {
+///         flag.alive();
+///     }).unwrap_err();
+///     let interrupt = payload.downcast_ref::<InterruptPanic>().unwrap();
+///     assert_eq!(interrupt.generation, control.generation());
+///     assert_eq!(interrupt.name.as_deref(), Some("indexer"));
+///     assert_eq!(interrupt.reason, None);
+///     assert_eq!(interrupt.to_string(), "thread interrupted by thread-control");
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InterruptPanic {
+    /// Message set by `Control::set_interrupt_message()` or the default one.
+    pub message: String,
+    /// Reason recorded by `Control::interrupt_traced()`.
+    pub reason: Option<String>,
+    /// Generation of the interrupted flag.
+    pub generation: u64,
+    /// Name set by `ControlBuilder::name()`.
+    pub name: Option<String>,
+}
+
+impl fmt::Display for InterruptPanic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.reason {
+            Some(ref reason) => write!(f, "{}: {}", self.message, reason),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+/// Reasons why `Control::shutdown()` or `ShutdownPlan::stop()` can't complete.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShutdownError {
+    /// The thread is still running after the timeout.
+    Timeout,
+}
+
+impl fmt::Display for ShutdownError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ShutdownError::Timeout => f.write_str("thread didn't finish in time"),
+        }
+    }
+}
+
+impl error::Error for ShutdownError {
+}
+
+// Status only moves from `Running` to a terminal one, the first terminal status wins
+fn advance_status(current: &AtomicU8, status: Status) {
+    debug_assert!(status.is_terminal(), "status can't move back to running");
+    let _ = current.compare_exchange(Status::Running.to_u8(), status.to_u8(),
+                                     Ordering::Release, Ordering::Relaxed);
+}
+
+// Accesses of the stop flag by the ordering of `ControlBuilder::ordering()`
+fn load_ordering(ordering: Ordering) -> Ordering {
+    match ordering {
+        Ordering::Relaxed => Ordering::Relaxed,
+        Ordering::SeqCst => Ordering::SeqCst,
+        _ => Ordering::Acquire,
+    }
+}
+
+fn store_ordering(ordering: Ordering) -> Ordering {
+    match ordering {
+        Ordering::Relaxed => Ordering::Relaxed,
+        Ordering::SeqCst => Ordering::SeqCst,
+        _ => Ordering::Release,
+    }
+}
+
+type Callback = Box<dyn FnOnce(Status) + Send>;
+
+// Entry of the ring of `Flag::record()` calls. `at` is nanoseconds since
+// `work_epoch` plus one, zero while the entry is empty or being written.
+#[derive(Debug, Default)]
+struct WorkRecord {
+    at: AtomicU64,
+    units: AtomicU64,
+}
+
+// Callbacks of `ControlBuilder::on_stop()`, called once by `Flag::finish()`
+struct OnStop(Mutex<Vec<Callback>>);
+
+impl fmt::Debug for OnStop {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("OnStop").finish()
+    }
+}
+
+// State shared by flags and controls of a pair in a single allocation.
+// Features extend it with fields instead of adding `Arc`s to both sides.
+#[derive(Debug)]
+struct Shared {
+    alive: AtomicBool,
+    interrupt: AtomicBool,
+    // Own interrupts of independent clones
+    interrupts: Mutex<Vec<Arc<AtomicBool>>>,
+    // Number of existing flags, the last one finishes
+    clones: AtomicUsize,
+    status: AtomicU8,
+    finished_at: AtomicU64,
+    paused: AtomicBool,
+    // Set while the worker is parked by `park_worker()`
+    parked: AtomicBool,
+    worker: Mutex<Option<thread::Thread>>,
+    reason: Mutex<Option<String>>,
+    message: Mutex<Option<String>>,
+    budget: AtomicI64,
+    completions: Mutex<Vec<mpsc::Sender<Status>>>,
+    handle: Mutex<Option<thread::JoinHandle<()>>>,
+    acked: AtomicBool,
+    taken: AtomicBool,
+    on_stop: OnStop,
+    // Number of existing controls, the last one can interrupt on drop
+    controls: AtomicUsize,
+    interrupt_on_last_drop: AtomicBool,
+    work: AtomicU64,
+    // Allocated by the first `Flag::record()`, written without locks
+    work_log: OnceLock<Box<[WorkRecord]>>,
+    work_next: AtomicUsize,
+    work_epoch: Instant,
+    checkpoint: AtomicU32,
+    started: AtomicBool,
+    // Set by `ControlBuilder::track_start()`
+    track_start: AtomicBool,
+    #[cfg(feature = "futures")]
+    // One per `Done` future, so concurrent futures are all woken.
+    // `AtomicWaker` isn't `RefUnwindSafe`, but a panic can't break the list.
+    wakers: AssertUnwindSafe<Mutex<Vec<Weak<AtomicWaker>>>>,
+    // Owned notifications of `Control::cancelled()` need an `Arc`
+    #[cfg(feature = "tokio")]
+    cancel: Arc<Notify>,
+    // Replaced by pools, so receivers of a previous pair don't see it running again
+    #[cfg(feature = "tokio")]
+    status_watch: Mutex<watch::Sender<Status>>,
+    #[cfg(all(unix, feature = "unix"))]
+    signal_thread: Mutex<Option<libc::pthread_t>>,
+    // Created on demand by `Flag::stop_eventfd()`
+    #[cfg(all(target_os = "linux", feature = "linux"))]
+    eventfd: Mutex<Option<std::fs::File>>,
+}
+
+impl Shared {
+    fn new(own_interrupt: Arc<AtomicBool>) -> Self {
+        Shared {
+            alive: AtomicBool::new(true),
+            interrupt: AtomicBool::new(false),
+            interrupts: Mutex::new(vec![own_interrupt]),
+            clones: AtomicUsize::new(1),
+            status: AtomicU8::new(Status::Running.to_u8()),
+            finished_at: AtomicU64::new(0),
+            paused: AtomicBool::new(false),
+            parked: AtomicBool::new(false),
+            worker: Mutex::new(None),
+            reason: Mutex::new(None),
+            message: Mutex::new(None),
+            budget: AtomicI64::new(i64::MAX),
+            completions: Mutex::new(Vec::new()),
+            handle: Mutex::new(None),
+            acked: AtomicBool::new(false),
+            taken: AtomicBool::new(false),
+            on_stop: OnStop(Mutex::new(Vec::new())),
+            controls: AtomicUsize::new(0),
+            interrupt_on_last_drop: AtomicBool::new(false),
+            work: AtomicU64::new(0),
+            work_log: OnceLock::new(),
+            work_next: AtomicUsize::new(0),
+            work_epoch: Instant::now(),
+            checkpoint: AtomicU32::new(0),
+            started: AtomicBool::new(false),
+            track_start: AtomicBool::new(false),
+            #[cfg(feature = "futures")]
+            wakers: AssertUnwindSafe(Mutex::new(Vec::new())),
+            #[cfg(feature = "tokio")]
+            cancel: Arc::new(Notify::new()),
+            #[cfg(feature = "tokio")]
+            status_watch: Mutex::new(watch::Sender::new(Status::Running)),
+            #[cfg(all(unix, feature = "unix"))]
+            signal_thread: Mutex::new(None),
+            #[cfg(all(target_os = "linux", feature = "linux"))]
+            eventfd: Mutex::new(None),
+        }
+    }
+
+    #[cfg(feature = "futures")]
+    fn wake_futures(&self) {
+        // Wakers of dropped futures are forgotten on the way
+        self.wakers.lock().expect("wakers poisoned").retain(|waker| {
+            match waker.upgrade() {
+                Some(waker) => {
+                    waker.wake();
+                    true
+                },
+                None => false,
+            }
+        });
+    }
+
+    fn any_interrupted(&self) -> bool {
+        self.interrupt.load(Ordering::Relaxed) || self.interrupts.lock()
+            .expect("interrupts poisoned")
+            .iter()
+            .any(|interrupt| (**interrupt).load(Ordering::Relaxed))
+    }
+}
 
 /// Struct to check execution status of spawned thread.
 #[derive(Debug)]
 pub struct Flag {
-    alive: Arc<AtomicBool>,
-    interrupt: Arc<AtomicBool>,
+    shared: Arc<Shared>,
+    own_interrupt: Arc<AtomicBool>,
+    generation: u64,
+    created_at: &'static Location<'static>,
+    name: Option<Arc<str>>,
+    ordering: Ordering,
+    watched: Vec<(Arc<AtomicBool>, bool)>,
+    scoped_panics: bool,
+    // Set when `alive()` of this flag starts unwinding
+    raised: AtomicBool,
+    // Flag is treated as stopped after it, see `make_pair_with_ttl()`
+    expires_at: Option<Instant>,
+    // Pairs of enclosing scopes, see `subscope()`
+    ancestors: Vec<Arc<Shared>>,
 }
 
 impl Drop for Flag {
     fn drop(&mut self) {
-        if thread::panicking() {
-            (*self.interrupt).store(true, Ordering::Relaxed)
+        if thread::panicking() && (!self.scoped_panics || self.raised.load(Ordering::Relaxed)) {
+            counters::panic(self.name.as_deref());
+            (*self.own_interrupt).store(true, Ordering::Relaxed)
         }
+        if self.shared.clones.fetch_sub(1, Ordering::AcqRel) > 1 {
+            // Independent clones are still running
+            return;
+        }
+        self.finish();
+    }
+}
+
+impl Default for Flag {
+    fn default() -> Self {
+        Flag::new()
     }
 }
 
 impl Flag {
 
     /// Creates new flag.
+    #[track_caller]
     pub fn new() -> Self {
+        let own_interrupt = Arc::new(AtomicBool::new(false));
         Flag {
-            alive: Arc::new(AtomicBool::new(true)),
-            interrupt: Arc::new(AtomicBool::new(false)),
+            shared: Arc::new(Shared::new(own_interrupt.clone())),
+            own_interrupt,
+            generation: GENERATION.fetch_add(1, Ordering::Relaxed),
+            created_at: Location::caller(),
+            name: None,
+            ordering: Ordering::Relaxed,
+            watched: Vec::new(),
+            scoped_panics: false,
+            raised: AtomicBool::new(false),
+            expires_at: None,
+            ancestors: Vec::new(),
         }
     }
 
+    /// Creates new flag which is already stopped.
+    #[track_caller]
+    pub fn new_stopped() -> Self {
+        let flag = Flag::new();
+        flag.shared.alive.store(false, Ordering::Relaxed);
+        flag
+    }
+
+    /// Creates new flag which is already interrupted.
+    #[track_caller]
+    pub fn new_interrupted() -> Self {
+        let flag = Flag::new();
+        flag.shared.interrupt.store(true, Ordering::Relaxed);
+        flag
+    }
+
     /// Creates new `Control` to control this flag.
     pub fn take_control(&self) -> Control {
+        self.shared.controls.fetch_add(1, Ordering::Relaxed);
         Control {
-            alive: Arc::downgrade(&self.alive),
-            interrupt: self.interrupt.clone(),
+            shared: self.shared.clone(),
+            generation: self.generation,
+            created_at: self.created_at,
+            name: self.name.clone(),
+            ordering: self.ordering,
+            detached: false,
+            owning: true,
+        }
+    }
+
+    /// Creates a clone which shares stop state with this flag,
+    /// but can be interrupted separately with `Control::interrupt_one()`.
+    /// `Control::interrupt()` still interrupts all clones.
+    /// The control is done when all clones are dropped.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use std::thread;
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    ///     let (flag, control) = make_pair();
+    ///     let sibling = flag.clone_independent();
+    ///     let victim = thread::spawn(move || {
+    ///         while flag.alive() {
+    ///         }
+    ///     });
+    ///     let survivor = thread::spawn(move || {
+    ///         while sibling.alive() {
+    ///         }
+    ///     });
+    ///     assert_eq!(control.interrupt_one(0), true);
+    ///     assert!(victim.join().is_err());
+    ///     assert_eq!(control.is_interrupted(), true);
+    ///     assert_eq!(control.is_done(), false);
+    ///     control.stop();
+    ///     assert!(survivor.join().is_ok());
+    ///     assert_eq!(control.is_done(), true);
+    /// }
+    /// ```
+    pub fn clone_independent(&self) -> Flag {
+        let own_interrupt = Arc::new(AtomicBool::new(false));
+        self.shared.interrupts.lock().expect("interrupts poisoned").push(own_interrupt.clone());
+        self.shared.clones.fetch_add(1, Ordering::Relaxed);
+        Flag {
+            shared: self.shared.clone(),
+            own_interrupt,
+            generation: self.generation,
+            created_at: self.created_at,
+            name: self.name.clone(),
+            ordering: self.ordering,
+            watched: self.watched.clone(),
+            scoped_panics: self.scoped_panics,
+            raised: AtomicBool::new(false),
+            expires_at: self.expires_at,
+            ancestors: self.ancestors.clone(),
+        }
+    }
+
+    /// Creates a flag of a new pair for a nested scope, e.g. a sub-worker.
+    /// It's alive only while both it and this flag aren't stopped, but
+    /// it can be stopped separately by its own `Control` made with
+    /// `take_control()` without affecting this flag. Scopes nest, a stop
+    /// of any enclosing scope stops the flag. Interrupts aren't shared,
+    /// every scope is interrupted by its own control.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    ///     let (flag, control) = make_pair();
+    ///     let child = flag.subscope();
+    ///     let child_control = child.take_control();
+    ///     let grandchild = child.subscope();
+    ///     // Stop of the child doesn't reach the parent
+    ///     child_control.stop();
+    ///     assert_eq!(child.alive(), false);
+    ///     assert_eq!(grandchild.alive(), false);
+    ///     assert_eq!(flag.alive(), true);
+    ///
+    ///     let child = flag.subscope();
+    ///     let grandchild = child.subscope();
+    ///     assert_eq!(grandchild.alive(), true);
+    ///     // Stop of the parent reaches all nested scopes
+    ///     control.stop();
+    ///     assert_eq!(flag.alive(), false);
+    ///     assert_eq!(child.alive(), false);
+    ///     assert_eq!(grandchild.alive(), false);
+    /// }
+    /// ```
+    #[track_caller]
+    pub fn subscope(&self) -> Flag {
+        let mut flag = Flag::new();
+        flag.ancestors = self.ancestors.clone();
+        flag.ancestors.push(self.shared.clone());
+        flag.watched = self.watched.clone();
+        flag.expires_at = self.expires_at;
+        flag
+    }
+
+    /// Mark the flag finished, so `Control::is_done()` returns `true`
+    /// even though the flag isn't dropped yet. It's dropped automatically.
+    /// Use it with flags shared by `Arc` which can outlive the worker.
+    pub fn finish(&self) {
+        // Zero is reserved for "still running"
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH)
+            .map(|since| since.as_nanos() as u64)
+            .unwrap_or(0)
+            .max(1);
+        // Published before the status and `alive` are released,
+        // so it's visible once `is_done()`
+        let _ = self.shared.finished_at.compare_exchange(0, nanos, Ordering::Release, Ordering::Relaxed);
+        let never_started = self.shared.track_start.load(Ordering::Relaxed)
+            && !self.shared.started.load(Ordering::Acquire);
+        let status = if never_started {
+            Status::NeverStarted
+        } else if self.shared.any_interrupted() {
+            Status::Interrupted
+        } else if self.is_stopped() {
+            Status::Stopped
+        } else {
+            Status::Finished
+        };
+        advance_status(&self.shared.status, status);
+        let status = Status::from_u8(self.shared.status.load(Ordering::Acquire));
+        for completion in self.shared.completions.lock().expect("completions poisoned").drain(..) {
+            // Receiver could be dropped already
+            let _ = completion.send(status);
+        }
+        let callbacks: Vec<_> = self.shared.on_stop.0.lock().expect("callbacks poisoned").drain(..).collect();
+        for callback in callbacks {
+            callback(status);
+        }
+        #[cfg(feature = "futures")]
+        self.shared.wake_futures();
+        #[cfg(feature = "tokio")]
+        self.shared.cancel.notify_waiters();
+        #[cfg(feature = "tokio")]
+        self.shared.status_watch.lock().expect("status watch poisoned").send_replace(status);
+        #[cfg(all(unix, feature = "unix"))]
+        signal::forget(&self.shared.signal_thread);
+        #[cfg(feature = "registry")]
+        {
+            if let Some(ref name) = self.name {
+                registry::deregister(name, self.generation);
+            }
+        }
+    }
+
+    /// Run `body` and catch its panic, also the one raised by `alive()`
+    /// on interrupt, instead of unwinding the thread. A panic is recorded
+    /// like a panic of the thread and the flag is dropped afterwards.
+    /// Returns the status after the drop, `Running` if other clones
+    /// made by `clone_independent()` are still alive.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use std::thread;
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    ///     let (flag, control) = make_pair();
+    ///     let handle = thread::spawn(move || {
+    ///         flag.run_catching(|flag| {
+    ///             while flag.alive() {
+    ///             }
+    ///         })
+    ///     });
+    ///     control.interrupt();
+    ///     assert_eq!(handle.join().unwrap(), Status::Interrupted);
+    ///
+    ///     let (flag, _control) = make_pair();
+    ///     let status = flag.run_catching(|_flag| {
+    ///         panic!("worker failed");
+    ///     });
+    ///     assert_eq!(status, Status::Interrupted);
+    ///
+    ///     let (flag, _control) = make_pair();
+    ///     let status = flag.run_catching(|_flag| {
+    ///     });
+    ///     assert_eq!(status, Status::Finished);
+    /// }
+    /// ```
+    pub fn run_catching<F>(self, body: F) -> Status
+        where F: FnOnce(&Flag) + panic::UnwindSafe
+    {
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| body(&self)));
+        // Same as the drop while panicking, which doesn't happen here
+        if result.is_err() && (!self.scoped_panics || self.raised.load(Ordering::Relaxed)) {
+            counters::panic(self.name.as_deref());
+            (*self.own_interrupt).store(true, Ordering::Relaxed)
+        }
+        let shared = self.shared.clone();
+        drop(self);
+        Status::from_u8(shared.status.load(Ordering::Acquire))
+    }
+
+    /// Register cleanup which runs with the final status when the flag
+    /// is done, however the worker exits: by stop, by interrupt or other
+    /// panic, or normally. It's called by the finishing thread like
+    /// callbacks of `ControlBuilder::on_stop()`, or at once if the flag
+    /// was already finished with `finish()`.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use std::sync::{Arc, Mutex};
+    /// use std::thread;
+    /// use thread_control::*;
+    ///
+    /// fn run(stop: bool, interrupt: bool) -> Vec<Status> {
+    ///     let exits = Arc::new(Mutex::new(Vec::new()));
+    ///     let (flag, control) = make_pair();
+    ///     let worker_exits = exits.clone();
+    ///     flag.on_exit(move |status| worker_exits.lock().unwrap().push(status));
+    ///     if stop {
+    ///         control.stop();
+    ///     }
+    ///     if interrupt {
+    ///         control.interrupt();
+    ///     }
+    ///     let _ = thread::spawn(move || {
+    ///         flag.alive();
+    ///     }).join();
+    ///     let result = exits.lock().unwrap().clone();
+    ///     result
+    /// }
+    ///
+    /// fn main() {
+    ///     assert_eq!(run(false, false), vec![Status::Finished]);
+    ///     assert_eq!(run(true, false), vec![Status::Stopped]);
+    ///     assert_eq!(run(false, true), vec![Status::Interrupted]);
+    /// }
+    /// ```
+    pub fn on_exit<F>(&self, f: F)
+        where F: FnOnce(Status) + Send + 'static
+    {
+        let mut callbacks = self.shared.on_stop.0.lock().expect("callbacks poisoned");
+        // The status is set before the finishing thread takes the lock
+        let status = Status::from_u8(self.shared.status.load(Ordering::Acquire));
+        if status.is_terminal() {
+            drop(callbacks);
+            f(status);
+        } else {
+            callbacks.push(Box::new(f));
+        }
+    }
+
+    /// Mark the worker started, call it at the entry of the worker.
+    /// Flags of pairs built with `ControlBuilder::track_start()`
+    /// finish with `Status::NeverStarted` without it.
+    pub fn mark_started(&self) {
+        self.shared.started.store(true, Ordering::Release);
+    }
+
+    /// Register the current thread as the worker of this flag,
+    /// so it can be parked with `Control::park_worker()`.
+    /// Only the last registered thread is kept.
+    pub fn register_current_thread(&self) {
+        *self.shared.worker.lock().expect("worker poisoned") = Some(thread::current());
+    }
+
+    /// Return `true` if the thread is unwinding because of interrupt.
+    /// It's useful in `Drop` of types which keep the flag
+    /// to decide how much cleanup to do.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use std::panic;
+    /// use std::sync::{Arc, Mutex};
+    /// use thread_control::*;
+    ///
+    /// struct Worker {
+    ///     flag: Flag,
+    ///     log: Arc<Mutex<Vec<&'static str>>>,
+    /// }
+    ///
+    /// impl Drop for Worker {
+    ///     fn drop(&mut self) {
+    ///         let cleanup = if self.flag.dropping_due_to_panic() { "minimal" } else { "full" };
+    ///         self.log.lock().unwrap().push(cleanup);
+    ///     }
+    /// }
+    ///
+    /// fn main() {
+    ///     let log = Arc::new(Mutex::new(Vec::new()));
+    ///     let (flag, _control) = make_pair();
+    ///     drop(Worker { flag, log: log.clone() });
+    ///     let (flag, control) = make_pair();
+    ///     let worker = Worker { flag, log: log.clone() };
+    ///     control.interrupt();
+    ///     let _ = panic::catch_unwind(panic::AssertUnwindSafe(move || {
+    ///         while worker.flag.alive() {
+    ///         }
+    ///     }));
+    ///     assert_eq!(*log.lock().unwrap(), vec!["full", "minimal"]);
+    /// }
+    /// ```
+    pub fn dropping_due_to_panic(&self) -> bool {
+        thread::panicking() && self.is_interrupted()
+    }
+
+    fn is_interrupted(&self) -> bool {
+        self.shared.interrupt.load(Ordering::Relaxed) || (*self.own_interrupt).load(Ordering::Relaxed)
+    }
+
+    fn is_stopped(&self) -> bool {
+        !self.shared.alive.load(load_ordering(self.ordering))
+            || GLOBAL_STOP.load(Ordering::Relaxed)
+            || self.watched.iter().any(|&(ref external, stop_when)| {
+                external.load(Ordering::Relaxed) == stop_when
+            })
+            || self.expires_at.is_some_and(|expires_at| Instant::now() >= expires_at)
+            || self.ancestors.iter().any(|ancestor| !ancestor.alive.load(load_ordering(self.ordering)))
+    }
+
+    /// Also treat the flag as stopped when `external` becomes `stop_when`.
+    /// It bridges the flag with other shutdown mechanisms.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use std::sync::Arc;
+    /// use std::sync::atomic::{AtomicBool, Ordering};
+    /// use std::thread;
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    ///     let running = Arc::new(AtomicBool::new(true));
+    ///     let (mut flag, control) = make_pair();
+    ///     flag.also_watch(running.clone(), false);
+    ///     let handle = thread::spawn(move || {
+    ///         while flag.alive() {
+    ///         }
+    ///     });
+    ///     running.store(false, Ordering::Relaxed);
+    ///     handle.join().unwrap();
+    ///     assert_eq!(control.status(), Status::Stopped);
+    /// }
+    /// ```
+    pub fn also_watch(&mut self, external: Arc<AtomicBool>, stop_when: bool) {
+        self.watched.push((external, stop_when));
+    }
+
+    /// Link panics of the thread to the flag only if they were raised
+    /// by `alive()` of this flag. By default any panic while the flag
+    /// is dropped marks it interrupted, even one of unrelated code.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use std::panic;
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    ///     let (mut flag, control) = make_pair();
+    ///     flag.scope_panics_to_alive();
+    ///     let result = panic::catch_unwind(move || {
+    ///         let _flag = flag;
+    ///         panic!("unrelated failure");
+    ///     });
+    ///     assert!(result.is_err());
+    ///     assert_eq!(control.is_interrupted(), false);
+    ///     assert_eq!(control.status(), Status::Finished);
+    ///
+    ///     let (mut flag, control) = make_pair();
+    ///     flag.scope_panics_to_alive();
+    ///     control.interrupt();
+    ///     let result = panic::catch_unwind(move || {
+    ///         flag.alive();
+    ///     });
+    ///     assert!(result.is_err());
+    ///     assert_eq!(control.status(), Status::Interrupted);
+    /// }
+    /// ```
+    pub fn scope_panics_to_alive(&mut self) {
+        self.scoped_panics = true;
+    }
+
+    #[cold]
+    #[inline(never)]
+    fn raise_interrupt(&self) -> ! {
+        self.raised.store(true, Ordering::Relaxed);
+        interrupted(&self.shared.acked, &self.shared.message, &self.shared.reason,
+                    self.generation, self.name.as_deref())
+    }
+
+    fn park_while_paused(&self) {
+        self.shared.parked.store(true, Ordering::Release);
+        while self.shared.paused.load(Ordering::Relaxed)
+            && !self.is_stopped()
+            && !self.is_interrupted() {
+            thread::park();
         }
+        self.shared.parked.store(false, Ordering::Release);
     }
 
     /// Check the flag isn't stopped or interrupted.
+    /// Also returns `false` after `global_stop()`.
     ///
     /// # Panics
     ///
     /// This method panics, if interrupt flag was set.
     pub fn alive(&self) -> bool {
-        if (*self.interrupt).load(Ordering::Relaxed) {
-            panic!("thread interrupted by thread-contol");
+        if self.is_interrupted() {
+            self.raise_interrupt();
+        }
+        if self.shared.paused.load(Ordering::Relaxed) {
+            self.park_while_paused();
+            if self.is_interrupted() {
+                self.raise_interrupt();
+            }
+        }
+        !self.is_stopped()
+    }
+
+    /// Check the flag like `alive()` and synchronize with
+    /// `Control::stop_release()`: once it returns `false` after the stop,
+    /// all writes made before the stop are visible to the thread.
+    ///
+    /// # Panics
+    ///
+    /// This method panics, if interrupt flag was set.
+    pub fn alive_acquire(&self) -> bool {
+        let alive = self.alive();
+        // Pairs with the release store of `Control::stop_release()`
+        fence(Ordering::Acquire);
+        alive
+    }
+
+    /// Check the flag like `alive()`, but return `None` instead of
+    /// panicking if interrupt flag was set. Deeply nested code returns
+    /// up the stack with `?` then and doesn't pay for unwinding.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use std::panic::{self, AssertUnwindSafe};
+    /// use thread_control::*;
+    ///
+    /// fn descend(flag: &Flag, depth: u32) -> Option<u32> {
+    ///     if depth == 100 || !flag.alive_or_return()? {
+    ///         return Some(depth);
+    ///     }
+    ///     descend(flag, depth + 1)
+    /// }
+    ///
+    /// fn main() {
+    ///     let actions: Vec<fn(&Control)> = vec![|_| {}, Control::stop, Control::interrupt];
+    ///     for action in actions {
+    ///         let (flag, control) = make_pair();
+    ///         action(&control);
+    ///         let unwound = panic::catch_unwind(AssertUnwindSafe(|| flag.alive())).ok();
+    ///         assert_eq!(flag.alive_or_return(), unwound);
+    ///     }
+    ///
+    ///     let (flag, control) = make_pair();
+    ///     assert_eq!(descend(&flag, 0), Some(100));
+    ///     control.interrupt();
+    ///     assert_eq!(descend(&flag, 0), None);
+    ///     assert_eq!(control.interrupt_acknowledged(), true);
+    /// }
+    /// ```
+    pub fn alive_or_return(&self) -> Option<bool> {
+        if self.is_interrupted() {
+            self.shared.acked.store(true, Ordering::Release);
+            return None;
+        }
+        if self.shared.paused.load(Ordering::Relaxed) {
+            self.park_while_paused();
+            if self.is_interrupted() {
+                self.shared.acked.store(true, Ordering::Release);
+                return None;
+            }
+        }
+        Some(!self.is_stopped())
+    }
+
+    /// Wraps `reader`, so every read checks the flag with `alive()`
+    /// first and fails once the flag is stopped. Long copies like
+    /// `io::copy()` abort promptly this way.
+    ///
+    /// The error has `ErrorKind::Other`, because `ErrorKind::Interrupted`
+    /// is retried by `io::copy()`, `read_exact()` and friends forever.
+    ///
+    /// # Panics
+    ///
+    /// Reading panics, if interrupt flag was set.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use std::io::{self, Read};
+    /// use std::thread;
+    /// use std::time::Duration;
+    /// use thread_control::*;
+    ///
+    /// struct SlowReader;
+    ///
+    /// impl Read for SlowReader {
+    ///     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    ///         thread::sleep(Duration::from_millis(5));
+    ///         buf[0] = 1;
+    ///         Ok(1)
+    ///     }
+    /// }
+    ///
+    /// fn main() {
+    ///     let (flag, control) = make_pair();
+    ///     let handle = thread::spawn(move || {
+    ///         let mut reader = flag.wrap_read(SlowReader);
+    ///         io::copy(&mut reader, &mut io::sink())
+    ///     });
+    ///     thread::sleep(Duration::from_millis(50));
+    ///     control.stop();
+    ///     let err = handle.join().unwrap().unwrap_err();
+    ///     assert_eq!(err.kind(), io::ErrorKind::Other);
+    ///     assert_eq!(err.to_string(), "stopped by thread-control");
+    /// }
+    /// ```
+    pub fn wrap_read<R: io::Read>(&self, reader: R) -> AliveRead<'_, R> {
+        AliveRead {
+            flag: self,
+            inner: reader,
+        }
+    }
+
+    /// Wraps `writer`, so every write and flush checks the flag with
+    /// `alive()` first and fails once the flag is stopped like reads
+    /// of `wrap_read()`.
+    ///
+    /// # Panics
+    ///
+    /// Writing panics, if interrupt flag was set.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use std::io::{self, Write};
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    ///     let (flag, control) = make_pair();
+    ///     let mut writer = flag.wrap_write(Vec::new());
+    ///     writer.write_all(b"first").unwrap();
+    ///     control.stop();
+    ///     let err = writer.write_all(b"second").unwrap_err();
+    ///     assert_eq!(err.kind(), io::ErrorKind::Other);
+    ///     assert_eq!(writer.into_inner(), b"first");
+    /// }
+    /// ```
+    pub fn wrap_write<W: io::Write>(&self, writer: W) -> AliveWrite<'_, W> {
+        AliveWrite {
+            flag: self,
+            inner: writer,
+        }
+    }
+
+    fn check_io(&self) -> io::Result<()> {
+        if self.alive() {
+            Ok(())
+        } else {
+            Err(io::Error::other("stopped by thread-control"))
+        }
+    }
+
+    /// Makes flag which reads the shared state at most once per `interval`
+    /// and uses the cached result between the checks.
+    pub fn throttled_by_time(self, interval: Duration) -> TimedFlag {
+        TimedFlag {
+            flag: self,
+            interval,
+            checked: Cell::new(None),
+        }
+    }
+
+    /// Makes flag which reads the shared state only once per `checks`
+    /// calls of `alive()` to reduce cross-core traffic in large pools.
+    pub fn cached(self, checks: u32) -> CachedFlag {
+        CachedFlag {
+            flag: self,
+            checks,
+            remaining: Cell::new(0),
+        }
+    }
+
+    /// Check the flag is alive like `alive()` and spend a unit of the budget
+    /// granted with `Control::grant_budget()`. Returns `false` when the
+    /// budget is exhausted, so the worker can yield. Without a grant
+    /// the budget is unlimited.
+    ///
+    /// # Panics
+    ///
+    /// This method panics, if interrupt flag was set.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    ///     let (flag, control) = make_pair();
+    ///     control.grant_budget(5);
+    ///     for _ in 0..5 {
+    ///         assert_eq!(flag.tick(), true);
+    ///     }
+    ///     assert_eq!(flag.tick(), false);
+    ///     control.grant_budget(1);
+    ///     assert_eq!(flag.tick(), true);
+    /// }
+    /// ```
+    pub fn tick(&self) -> bool {
+        if !self.alive() || self.shared.budget.load(Ordering::Relaxed) <= 0 {
+            return false;
+        }
+        self.shared.budget.fetch_sub(1, Ordering::Relaxed) > 0
+    }
+
+    /// Mark that the worker passed checkpoint `id`, so
+    /// `Control::interrupt_if_past()` can interrupt it safely.
+    /// Checkpoints only advance, passing a lower one is ignored.
+    pub fn checkpoint(&self, id: u32) {
+        self.shared.checkpoint.fetch_max(id, Ordering::AcqRel);
+    }
+
+    /// Record `units` of work done, so the controller can throttle
+    /// the worker by `Control::throughput()`. It doesn't take locks,
+    /// so it can be called from the hot loop next to `alive()`.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    ///     let (flag, control) = make_pair();
+    ///     flag.record(3);
+    ///     flag.record(4);
+    ///     assert_eq!(control.work_done(), 7);
+    /// }
+    /// ```
+    pub fn record(&self, units: u64) {
+        let shared = &*self.shared;
+        shared.work.fetch_add(units, Ordering::Relaxed);
+        let log = shared.work_log.get_or_init(|| (0..WORK_LOG_LEN).map(|_| WorkRecord::default()).collect());
+        // The oldest entry is overwritten
+        let record = &log[shared.work_next.fetch_add(1, Ordering::Relaxed) % WORK_LOG_LEN];
+        let at = shared.work_epoch.elapsed().as_nanos() as u64 + 1;
+        record.at.store(0, Ordering::Relaxed);
+        fence(Ordering::Release);
+        record.units.store(units, Ordering::Relaxed);
+        record.at.store(at, Ordering::Release);
+    }
+
+    /// Return the state of the flag as bits for custom checks:
+    ///
+    /// * `STATE_ALIVE` (`0b001`) - isn't stopped, also by `global_stop()`
+    /// * `STATE_INTERRUPTED` (`0b010`) - is interrupted
+    /// * `STATE_PAUSED` (`0b100`) - is paused by `Control::park_worker()`
+    ///
+    /// The layout is stable, other bits are always zero. The bits are
+    /// read one by one, so they aren't a snapshot of a single moment.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    ///     let (flag, control) = make_pair();
+    ///     assert_eq!(flag.state_bits(), STATE_ALIVE);
+    ///     control.stop();
+    ///     assert_eq!(flag.state_bits(), 0);
+    ///     control.interrupt();
+    ///     assert_eq!(flag.state_bits(), STATE_INTERRUPTED);
+    ///
+    ///     let (flag, control) = make_pair();
+    ///     flag.register_current_thread();
+    ///     control.park_worker();
+    ///     assert_eq!(flag.state_bits(), STATE_ALIVE | STATE_PAUSED);
+    /// }
+    /// ```
+    pub fn state_bits(&self) -> u8 {
+        let mut bits = 0;
+        if !self.is_stopped() {
+            bits |= STATE_ALIVE;
+        }
+        if self.is_interrupted() {
+            bits |= STATE_INTERRUPTED;
+        }
+        if self.shared.paused.load(Ordering::Relaxed) {
+            bits |= STATE_PAUSED;
         }
-        (*self.alive).load(Ordering::Relaxed)
+        bits
     }
 
     /// Check the flag is not stopped and not interrupted
     /// Use it if panic is not desirable behavior
     pub fn is_alive(&self) -> bool {
-        (*self.alive).load(Ordering::Relaxed) && !(*self.interrupt).load(Ordering::Relaxed)
+        !self.is_stopped() && !self.is_interrupted()
     }
 
     /// Set interrupt flag and drop the instance
     pub fn interrupt(self) {
-        (self.interrupt).store(true, Ordering::Relaxed)
+        counters::interrupt(self.name.as_deref());
+        (self.own_interrupt).store(true, Ordering::Relaxed)
     }
 }
 
+// Kept out of line to leave the hot path of `alive()` straight
+#[cold]
+#[inline(never)]
+fn interrupted(acked: &AtomicBool, message: &Mutex<Option<String>>, reason: &Mutex<Option<String>>,
+               generation: u64, name: Option<&str>) -> ! {
+    acked.store(true, Ordering::Release);
+    let message = message.lock().expect("message poisoned")
+        .clone()
+        .unwrap_or_else(|| "thread interrupted by thread-control".to_owned());
+    panic::panic_any(InterruptPanic {
+        message,
+        reason: reason.lock().expect("reason poisoned").clone(),
+        generation,
+        name: name.map(str::to_owned),
+    })
+}
+
 /// Struct to control thread execution.
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct Control {
-    alive: Weak<AtomicBool>,
-    interrupt: Arc<AtomicBool>,
+    shared: Arc<Shared>,
+    generation: u64,
+    created_at: &'static Location<'static>,
+    name: Option<Arc<str>>,
+    ordering: Ordering,
+    // Set by `detach()` to drop without `interrupt_on_last_drop()`
+    detached: bool,
+    // Counted in `controls`, controls made by `WeakControl::upgrade()` aren't
+    owning: bool,
 }
 
-impl Control {
-    /// Interrupt execution of thread.
-    /// Actually it panics when thread checking flag.
-    pub fn interrupt(&self) {
-        (*self.interrupt).store(true, Ordering::Relaxed)
+impl Clone for Control {
+    fn clone(&self) -> Self {
+        if self.owning {
+            self.shared.controls.fetch_add(1, Ordering::Relaxed);
+        }
+        Control {
+            shared: self.shared.clone(),
+            generation: self.generation,
+            created_at: self.created_at,
+            name: self.name.clone(),
+            ordering: self.ordering,
+            detached: false,
+            owning: self.owning,
+        }
+    }
+}
+
+impl Drop for Control {
+    fn drop(&mut self) {
+        if !self.owning {
+            return;
+        }
+        // A stopped worker finishes gracefully on its own
+        if self.shared.controls.fetch_sub(1, Ordering::AcqRel) == 1
+            && !self.detached
+            && self.shared.interrupt_on_last_drop.load(Ordering::Relaxed)
+            && self.shared.alive.load(Ordering::Relaxed)
+            && !self.is_done() {
+            self.interrupt();
+        }
     }
+}
 
-    /// Set stop flag.
-    pub fn stop(&self) {
-        self.alive.upgrade().map(|flag| {
-            (*flag).store(false, Ordering::Relaxed)
-        });
+impl PartialEq for Control {
+    fn eq(&self, other: &Control) -> bool {
+        self.generation == other.generation
     }
+}
 
-    /// Return `true` if thread ended.
-    pub fn is_done(&self) -> bool {
-        self.alive.upgrade().is_none()
+impl Eq for Control {
+}
+
+/// Controls hash by the generation like they compare, so clones
+/// are one key of a `HashSet` or `HashMap`.
+///
+/// Example:
+///
+/// ```rust
+/// use std::collections::HashSet;
+/// use thread_control::*;
+///
+/// fn main() {
+///     let (_first_flag, first) = make_pair();
+///     let (_second_flag, second) = make_pair();
+///     let mut controls = HashSet::new();
+///     controls.insert(first.clone());
+///     controls.insert(first.clone());
+///     controls.insert(second.clone());
+///     controls.insert(first);
+///     assert_eq!(controls.len(), 2);
+///     assert!(controls.contains(&second));
+/// }
+/// ```
+impl Hash for Control {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.generation.hash(state);
     }
+}
 
-    /// Return `true` if thread was interrupted or panicked.
-    pub fn is_interrupted(&self) -> bool {
-        (*self.interrupt).load(Ordering::Relaxed)
+impl PartialOrd for Control {
+    fn partial_cmp(&self, other: &Control) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
     }
 }
 
-/// Makes pair with connected flag and control.
-pub fn make_pair() -> (Flag, Control) {
-    let flag = Flag::new();
-    let control = flag.take_control();
-    (flag, control)
+/// Controls are ordered by creation of their flags.
+///
+/// Example:
+///
+/// ```rust
+/// use thread_control::*;
+///
+/// fn main() {
+///     let (_first_flag, first) = make_pair();
+///     let (_second_flag, second) = make_pair();
+///     let (_third_flag, third) = make_pair();
+///     let mut controls = vec![third.clone(), first.clone(), second.clone()];
+///     controls.sort();
+///     assert_eq!(controls, vec![first, second, third]);
+/// }
+/// ```
+impl Ord for Control {
+    fn cmp(&self, other: &Control) -> cmp::Ordering {
+        self.generation.cmp(&other.generation)
+    }
 }
 
+impl Control {
+    /// Creates builder of a pair with optional features.
+    /// Use `make_pair()` if you don't need them.
+    pub fn builder() -> ControlBuilder {
+        ControlBuilder {
+            name: None,
+            ordering: Ordering::Relaxed,
+            on_stop: Vec::new(),
+            watchdog: None,
+            interrupt_on_panic: true,
+            track_start: false,
+        }
+    }
+
+    /// Interrupt execution of thread.
+    /// Actually it panics when thread checking flag.
+    pub fn interrupt(&self) {
+        counters::interrupt(self.name());
+        self.shared.interrupt.store(true, Ordering::Relaxed);
+        self.shared.taken.store(false, Ordering::Release);
+        self.notify();
+    }
+
+    /// Interrupt execution of thread and keep the backtrace of the caller
+    /// as the interrupt reason, so the panic of the worker shows where
+    /// the interrupt came from. The backtrace is captured only if
+    /// backtraces are enabled with `RUST_LIB_BACKTRACE` or `RUST_BACKTRACE`.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use std::env;
+    /// use thread_control::*;
+    ///
+    /// #[inline(never)]
+    /// fn shutdown_worker(control: &Control) {
+    ///     control.interrupt_traced();
+    /// }
+    ///
+    /// fn main() {
+    ///     env::set_var("RUST_LIB_BACKTRACE", "1");
+    ///     let (_flag, control) = make_pair();
+    ///     shutdown_worker(&control);
+    ///     assert_eq!(control.is_interrupted(), true);
+    ///     assert!(control.interrupt_reason().unwrap().contains("shutdown_worker"));
+    /// }
+    /// ```
+    pub fn interrupt_traced(&self) {
+        let backtrace = Backtrace::capture();
+        if backtrace.status() == BacktraceStatus::Captured {
+            let reason = format!("interrupt requested at:\n{}", backtrace);
+            *self.shared.reason.lock().expect("reason poisoned") = Some(reason);
+        }
+        self.interrupt();
+    }
+
+    /// Return `true` if the worker observed the interrupt
+    /// in `Flag::alive()`, not only that it was requested.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use std::panic;
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    ///     let (flag, control) = make_pair();
+    ///     control.interrupt();
+    ///     assert_eq!(control.interrupt_acknowledged(), false);
+    ///     let result = panic::catch_unwind(move || {
+    ///         flag.alive();
+    ///     });
+    ///     assert!(result.is_err());
+    ///     assert_eq!(control.interrupt_acknowledged(), true);
+    /// }
+    /// ```
+    pub fn interrupt_acknowledged(&self) -> bool {
+        self.shared.acked.load(Ordering::Acquire)
+    }
+
+    /// Set the message of the panic raised by `Flag::alive()` on interrupt
+    /// instead of "thread interrupted by thread-control".
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use std::panic;
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    ///     let (flag, control) = make_pair();
+    ///     control.set_interrupt_message("indexer was shut down");
+    ///     control.interrupt();
+    ///     let payload = panic::catch_unwind(move || {
+    ///         flag.alive();
+    ///     }).unwrap_err();
+    ///     let interrupt = payload.downcast_ref::<InterruptPanic>().unwrap();
+    ///     assert_eq!(interrupt.message, "indexer was shut down");
+    /// }
+    /// ```
+    pub fn set_interrupt_message(&self, message: impl Into<String>) {
+        *self.shared.message.lock().expect("message poisoned") = Some(message.into());
+    }
+
+    /// Return the reason of interrupt if it was recorded.
+    pub fn interrupt_reason(&self) -> Option<String> {
+        self.shared.reason.lock().expect("reason poisoned").clone()
+    }
+
+    /// Interrupt execution of a single flag created with `Flag::clone_independent()`.
+    /// The original flag has index `0`, clones are numbered in order of creation.
+    /// Returns `false` if there is no flag with `index`.
+    pub fn interrupt_one(&self, index: usize) -> bool {
+        let interrupts = self.shared.interrupts.lock().expect("interrupts poisoned");
+        match interrupts.get(index) {
+            Some(interrupt) => {
+                counters::interrupt(self.name());
+                (**interrupt).store(true, Ordering::Relaxed);
+                self.shared.taken.store(false, Ordering::Release);
+                self.notify();
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Set stop flag.
+    pub fn stop(&self) {
+        counters::stop(self.name());
+        self.shared.alive.store(false, store_ordering(self.ordering));
+        self.notify();
+    }
+
+    /// Set stop flag with `Ordering::Release`, so the worker which checks
+    /// the flag with `Flag::alive_acquire()` sees all writes made
+    /// before this call when it observes the stop.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use std::sync::{Arc, Mutex};
+    /// use std::thread;
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    ///     let results = Arc::new(Mutex::new(Vec::new()));
+    ///     let (flag, control) = make_pair();
+    ///     let worker_results = results.clone();
+    ///     let handle = thread::spawn(move || {
+    ///         while flag.alive_acquire() {
+    ///         }
+    ///         worker_results.lock().unwrap().len()
+    ///     });
+    ///     results.lock().unwrap().extend(0..100);
+    ///     control.stop_release();
+    ///     assert_eq!(handle.join().unwrap(), 100);
+    /// }
+    /// ```
+    pub fn stop_release(&self) {
+        counters::stop(self.name());
+        let ordering = match self.ordering {
+            Ordering::SeqCst => Ordering::SeqCst,
+            _ => Ordering::Release,
+        };
+        self.shared.alive.store(false, ordering);
+        self.notify();
+    }
+
+    /// Interrupt the thread only if it passed checkpoint `id`
+    /// with `Flag::checkpoint()`. Returns `true` if it was interrupted.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    ///     let (flag, control) = make_pair();
+    ///     flag.checkpoint(1);
+    ///     assert_eq!(control.interrupt_if_past(2), false);
+    ///     assert_eq!(control.is_interrupted(), false);
+    ///     flag.checkpoint(2);
+    ///     assert_eq!(control.interrupt_if_past(2), true);
+    ///     assert_eq!(control.is_interrupted(), true);
+    /// }
+    /// ```
+    pub fn interrupt_if_past(&self, id: u32) -> bool {
+        if self.shared.checkpoint.load(Ordering::Acquire) < id {
+            return false;
+        }
+        self.interrupt();
+        true
+    }
+
+    /// Interrupt the thread when the last control of it is dropped
+    /// while the thread is still running, so workers don't run away
+    /// if their controller is gone. It doesn't fire if the thread
+    /// was stopped or the last control was given up with `detach()`.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use std::thread;
+    /// use std::time::Duration;
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    ///     let (flag, control) = make_pair();
+    ///     let weak = control.downgrade();
+    ///     control.interrupt_on_last_drop();
+    ///     let handle = thread::spawn(move || {
+    ///         while flag.alive() {
+    ///         }
+    ///     });
+    ///     let another = control.clone();
+    ///     drop(control);
+    ///     assert_eq!(another.is_interrupted(), false);
+    ///     drop(another);
+    ///     assert!(handle.join().is_err());
+    ///     assert!(weak.upgrade().is_none());
+    ///
+    ///     // Stopped gracefully by the timer which drops the last control
+    ///     let (flag, control) = make_pair();
+    ///     control.interrupt_on_last_drop();
+    ///     let handle = thread::spawn(move || {
+    ///         while flag.alive() {
+    ///             thread::sleep(Duration::from_millis(1));
+    ///         }
+    ///     });
+    ///     control.stop_after(Duration::from_millis(10));
+    ///     assert!(handle.join().is_ok());
+    ///
+    ///     // Given up intentionally, the worker stops on its own
+    ///     let (flag, control) = make_pair();
+    ///     control.interrupt_on_last_drop();
+    ///     let handle = thread::spawn(move || {
+    ///         for _ in 0..50 {
+    ///             flag.alive();
+    ///             thread::sleep(Duration::from_millis(1));
+    ///         }
+    ///     });
+    ///     control.detach();
+    ///     assert!(handle.join().is_ok());
+    /// }
+    /// ```
+    pub fn interrupt_on_last_drop(&self) {
+        self.shared.interrupt_on_last_drop.store(true, Ordering::Relaxed);
+    }
+
+    /// Return the total units of work recorded with `Flag::record()`.
+    pub fn work_done(&self) -> u64 {
+        self.shared.work.load(Ordering::Relaxed)
+    }
+
+    /// Return units of work per second recorded with `Flag::record()`
+    /// during the last `window`. Only the most recent records are kept,
+    /// so very long windows can report less than was done.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use std::thread;
+    /// use std::time::Duration;
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    /// #     // Timing assertions don't hold under the slow interpreter
+    /// #     if cfg!(miri) { return; }
+    ///     let (flag, control) = make_pair();
+    ///     for _ in 0..10 {
+    ///         flag.record(5);
+    ///         thread::sleep(Duration::from_millis(10));
+    ///     }
+    ///     let throughput = control.throughput(Duration::from_millis(500));
+    ///     assert!((throughput - 100.0).abs() < 1.0);
+    ///     assert_eq!(control.throughput(Duration::from_millis(1)), 0.0);
+    /// }
+    /// ```
+    pub fn throughput(&self, window: Duration) -> f64 {
+        let secs = window.as_secs_f64();
+        if secs == 0.0 {
+            return 0.0;
+        }
+        let log = match self.shared.work_log.get() {
+            Some(log) => log,
+            None => return 0.0,
+        };
+        let now = self.shared.work_epoch.elapsed().as_nanos() as u64 + 1;
+        let since = now.saturating_sub(window.as_nanos() as u64);
+        // Entries which are rewritten meanwhile are skipped
+        let units: u64 = log.iter()
+            .filter_map(|record| {
+                let at = record.at.load(Ordering::Acquire);
+                let units = record.units.load(Ordering::Relaxed);
+                fence(Ordering::Acquire);
+                let unchanged = record.at.load(Ordering::Relaxed) == at;
+                (at != 0 && at >= since && unchanged).then_some(units)
+            })
+            .sum();
+        units as f64 / secs
+    }
+
+    /// Set the budget spent by `Flag::tick()` to `units`.
+    pub fn grant_budget(&self, units: i64) {
+        self.shared.budget.store(units, Ordering::Relaxed)
+    }
+
+    /// Give up control of the thread intentionally.
+    /// The worker runs until it decides to stop on its own.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use std::sync::Arc;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::thread;
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    ///     let (flag, control) = make_pair();
+    ///     let counter = Arc::new(AtomicUsize::new(0));
+    ///     let worker_counter = counter.clone();
+    ///     let handle = thread::spawn(move || {
+    ///         while flag.alive() && worker_counter.load(Ordering::Relaxed) < 1000 {
+    ///             worker_counter.fetch_add(1, Ordering::Relaxed);
+    ///         }
+    ///     });
+    ///     control.detach();
+    ///     handle.join().unwrap();
+    ///     assert_eq!(counter.load(Ordering::Relaxed), 1000);
+    /// }
+    /// ```
+    pub fn detach(mut self) {
+        // Controls taken from the flag later don't interrupt it either
+        if self.owning && self.shared.controls.load(Ordering::Acquire) == 1 {
+            self.shared.interrupt_on_last_drop.store(false, Ordering::Relaxed);
+        }
+        self.detached = true;
+    }
+
+    /// Stop the thread gracefully after `dur` unless it's already done.
+    /// The timer runs in a separate thread.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use std::thread;
+    /// use std::time::Duration;
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    ///     let (flag, control) = make_pair();
+    ///     let handle = thread::spawn(move || {
+    ///         while flag.alive() {
+    ///         }
+    ///     });
+    ///     control.clone().stop_after(Duration::from_millis(20));
+    ///     handle.join().unwrap();
+    ///     assert_eq!(control.is_interrupted(), false);
+    ///     assert_eq!(control.status(), Status::Stopped);
+    /// }
+    /// ```
+    pub fn stop_after(self, dur: Duration) {
+        thread::spawn(move || {
+            thread::sleep(dur);
+            if !self.is_done() {
+                self.stop();
+            }
+        });
+    }
+
+    /// Stop the thread now and interrupt it if it's not done within `grace`.
+    /// The timer runs in a separate thread and is cancelled
+    /// as soon as the thread is done.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use std::thread;
+    /// use std::time::Duration;
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    ///     let control = spawn(|flag| {
+    ///         while flag.alive() {
+    ///         }
+    ///     });
+    ///     control.clone().stop_then_interrupt(Duration::from_secs(30));
+    ///     assert_eq!(control.join_result().is_ok(), true);
+    ///     assert_eq!(control.status(), Status::Stopped);
+    ///
+    ///     let control = spawn(|flag| {
+    ///         // Ignores the stop, only the interrupt ends it
+    ///         loop {
+    ///             flag.alive();
+    ///             thread::sleep(Duration::from_millis(1));
+    ///         }
+    ///     });
+    ///     control.clone().stop_then_interrupt(Duration::from_millis(20));
+    ///     assert_eq!(control.join_result().is_err(), true);
+    ///     assert_eq!(control.status(), Status::Interrupted);
+    /// }
+    /// ```
+    pub fn stop_then_interrupt(self, grace: Duration) {
+        self.stop();
+        let completion = self.completion_receiver();
+        thread::spawn(move || {
+            if let Err(mpsc::RecvTimeoutError::Timeout) = completion.recv_timeout(grace) {
+                self.interrupt();
+            }
+        });
+    }
+
+    /// Stop the thread when a `ProcessExitGuard` is dropped,
+    /// e.g. at the end of `main()`. Controls of threads which are done
+    /// are forgotten on the next registration.
+    pub fn stop_on_process_exit(self) {
+        let mut controls = EXIT_CONTROLS.lock().expect("exit controls poisoned");
+        controls.retain(|control| !control.is_done());
+        controls.push(self);
+    }
+
+    /// Pause the worker registered with `Flag::register_current_thread()`.
+    /// The worker parks in the next `Flag::alive()` call until
+    /// `unpark_worker()`, `stop()` or `interrupt()` is called.
+    /// Returns `false` if no worker was registered.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use std::sync::Arc;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::thread;
+    /// use std::time::Duration;
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    ///     let (flag, control) = make_pair();
+    ///     let counter = Arc::new(AtomicUsize::new(0));
+    ///     let worker_counter = counter.clone();
+    ///     let handle = thread::spawn(move || {
+    ///         flag.register_current_thread();
+    ///         while flag.alive() {
+    ///             worker_counter.fetch_add(1, Ordering::Relaxed);
+    ///         }
+    ///     });
+    ///     while counter.load(Ordering::Relaxed) == 0 {
+    ///     }
+    ///     assert_eq!(control.park_worker(), true);
+    ///     while !control.is_worker_parked() {
+    ///         thread::yield_now();
+    ///     }
+    ///     let parked = counter.load(Ordering::Relaxed);
+    ///     thread::sleep(Duration::from_millis(50));
+    ///     assert_eq!(counter.load(Ordering::Relaxed), parked);
+    ///     control.unpark_worker();
+    ///     while counter.load(Ordering::Relaxed) == parked {
+    ///     }
+    ///     control.stop();
+    ///     handle.join().unwrap();
+    /// }
+    /// ```
+    pub fn park_worker(&self) -> bool {
+        if self.shared.worker.lock().expect("worker poisoned").is_none() {
+            return false;
+        }
+        self.shared.paused.store(true, Ordering::Relaxed);
+        true
+    }
+
+    /// Return `true` while the worker is parked by `park_worker()`.
+    pub fn is_worker_parked(&self) -> bool {
+        self.shared.parked.load(Ordering::Acquire)
+    }
+
+    /// Resume the worker paused by `park_worker()`.
+    pub fn unpark_worker(&self) {
+        self.shared.paused.store(false, Ordering::Relaxed);
+        self.unpark();
+    }
+
+    // Wake everything which waits for changes of the state
+    fn notify(&self) {
+        self.unpark();
+        #[cfg(feature = "futures")]
+        self.shared.wake_futures();
+        #[cfg(feature = "tokio")]
+        self.shared.cancel.notify_waiters();
+        #[cfg(all(target_os = "linux", feature = "linux"))]
+        eventfd::wake(&self.shared.eventfd);
+    }
+
+    fn unpark(&self) {
+        if let Some(ref worker) = *self.shared.worker.lock().expect("worker poisoned") {
+            worker.unpark();
+        }
+    }
+
+    /// Return `true` if the worker called `Flag::mark_started()`,
+    /// so a worker which failed to spawn isn't taken for the finished one.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use std::thread;
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    ///     let (flag, control) = Control::builder().track_start().build();
+    ///     // The worker is never spawned
+    ///     drop(flag);
+    ///     assert_eq!(control.is_done(), true);
+    ///     assert_eq!(control.has_started(), false);
+    ///     assert_eq!(control.status(), Status::NeverStarted);
+    ///
+    ///     let (flag, control) = Control::builder().track_start().build();
+    ///     let handle = thread::spawn(move || {
+    ///         flag.mark_started();
+    ///     });
+    ///     handle.join().unwrap();
+    ///     assert_eq!(control.has_started(), true);
+    ///     assert_eq!(control.status(), Status::Finished);
+    /// }
+    /// ```
+    pub fn has_started(&self) -> bool {
+        self.shared.started.load(Ordering::Acquire)
+    }
+
+    /// Return `true` if thread ended.
+    /// For shared flags it also becomes `true` after `Flag::finish()`.
+    ///
+    /// It's `true` right after `JoinHandle::join()` of a thread which owned
+    /// the flag: the flag is dropped with the closure before the thread ends,
+    /// the drop publishes the status with a release store and the join
+    /// synchronizes with the end of the thread.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use std::thread;
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    ///     for _ in 0..64 {
+    ///         let (flag, control) = make_pair();
+    ///         let handle = thread::spawn(move || {
+    ///             flag.alive();
+    ///         });
+    ///         handle.join().unwrap();
+    ///         assert_eq!(control.is_done(), true);
+    ///     }
+    /// }
+    /// ```
+    pub fn is_done(&self) -> bool {
+        self.status().is_terminal()
+    }
+
+    /// Return `true` if thread was interrupted or panicked.
+    pub fn is_interrupted(&self) -> bool {
+        self.shared.any_interrupted()
+    }
+
+    /// Return `true` once per interrupt, so a supervisor handles
+    /// each interrupt only once. Only the report is reset, the thread
+    /// still observes the interrupt.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    ///     let (flag, control) = make_pair();
+    ///     assert_eq!(control.take_interrupted(), false);
+    ///     control.interrupt();
+    ///     assert_eq!(control.take_interrupted(), true);
+    ///     assert_eq!(control.take_interrupted(), false);
+    ///     assert_eq!(control.is_interrupted(), true);
+    ///     assert_eq!(flag.is_alive(), false);
+    /// }
+    /// ```
+    pub fn take_interrupted(&self) -> bool {
+        self.is_interrupted() && !self.shared.taken.swap(true, Ordering::AcqRel)
+    }
+
+    /// Return unique number of the flag, which grows in order of creation.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Return the name set by `ControlBuilder::name()`.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Return the location where the flag was created.
+    /// It helps to find the origin of a leaked or interrupted thread.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    ///     let (_flag, control) = make_pair();
+    ///     assert_eq!(control.created_at().file(), file!());
+    ///     assert_eq!(control.created_at().line(), line!() - 2);
+    /// }
+    /// ```
+    pub fn created_at(&self) -> &'static Location<'static> {
+        self.created_at
+    }
+
+    /// Return current status of thread.
+    pub fn status(&self) -> Status {
+        Status::from_u8(self.shared.status.load(Ordering::Acquire))
+    }
+
+    /// Return channel which receives the final status when thread is done.
+    /// Receiving blocks without polling. The flag always sends the status,
+    /// `recv()` fails only if the flag was leaked without being dropped.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use std::thread;
+    /// use std::time::Duration;
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    ///     let (flag, control) = make_pair();
+    ///     let completion = control.completion_receiver();
+    ///     let handle = thread::spawn(move || {
+    ///         thread::sleep(Duration::from_millis(20));
+    ///         drop(flag);
+    ///     });
+    ///     assert_eq!(completion.recv(), Ok(Status::Finished));
+    ///     assert_eq!(control.completion_receiver().recv(), Ok(Status::Finished));
+    ///     handle.join().unwrap();
+    /// }
+    /// ```
+    pub fn completion_receiver(&self) -> mpsc::Receiver<Status> {
+        let (tx, rx) = mpsc::channel();
+        let mut completions = self.shared.completions.lock().expect("completions poisoned");
+        // The status is set before the flag takes the lock
+        let status = self.status();
+        if status.is_terminal() {
+            let _ = tx.send(status);
+        } else {
+            completions.push(tx);
+        }
+        rx
+    }
+
+    /// Return iterator which yields the status at the time of the call
+    /// and then blocks for each following transition.
+    /// It ends after a terminal status.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use std::thread;
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    ///     let (flag, control) = make_pair();
+    ///     let changes = control.status_changes();
+    ///     let handle = thread::spawn(move || {
+    ///         while flag.alive() {
+    ///         }
+    ///     });
+    ///     control.stop();
+    ///     let changes: Vec<_> = changes.collect();
+    ///     assert_eq!(changes, vec![Status::Running, Status::Stopped]);
+    ///     handle.join().unwrap();
+    ///     let changes: Vec<_> = control.status_changes().collect();
+    ///     assert_eq!(changes, vec![Status::Stopped]);
+    /// }
+    /// ```
+    pub fn status_changes(&self) -> StatusChanges {
+        // Taken first, the receiver gets the status set meanwhile
+        let first = self.status();
+        StatusChanges {
+            first: Some(first),
+            completion: Some(self.completion_receiver()),
+        }
+    }
+
+    /// Wait for the thread spawned with `spawn()` and return its result
+    /// with the panic payload if the thread panicked.
+    ///
+    /// Returns `Ok(())` if the thread wasn't spawned with `spawn()` or was
+    /// already joined. For threads spawned in other ways the payload is
+    /// only available through their own `JoinHandle::join()`.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    ///     let control = spawn(|flag| {
+    ///         while flag.alive() {
+    ///             panic!("worker failed");
+    ///         }
+    ///     });
+    ///     let payload = control.join_result().unwrap_err();
+    ///     assert_eq!(payload.downcast_ref::<&str>(), Some(&"worker failed"));
+    ///     assert_eq!(control.is_interrupted(), true);
+    /// }
+    /// ```
+    pub fn join_result(&self) -> thread::Result<()> {
+        let handle = self.shared.handle.lock().expect("handle poisoned").take();
+        match handle {
+            Some(handle) => handle.join(),
+            None => Ok(()),
+        }
+    }
+
+    /// Shut the thread down gracefully, then forcefully: stop it and
+    /// wait up to `timeout` for it to finish, if it doesn't, interrupt it
+    /// and wait up to `timeout` again. The thread spawned with `spawn()`
+    /// is joined, a panic caused by the interrupt isn't reported.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use std::thread;
+    /// use std::time::Duration;
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    /// #     // Timing assertions don't hold under the slow interpreter
+    /// #     if cfg!(miri) { return; }
+    ///     let control = spawn(|flag| {
+    ///         while flag.alive() {
+    ///         }
+    ///     });
+    ///     assert_eq!(control.clone().shutdown(Duration::from_secs(30)), Ok(()));
+    ///     assert_eq!(control.status(), Status::Stopped);
+    ///
+    ///     // Each stage waits the whole timeout for it
+    ///     let timeout = Duration::from_secs(1);
+    ///     let control = spawn(|flag| {
+    ///         // Ignores the stop, but panics on the interrupt
+    ///         loop {
+    ///             let _ = flag.alive();
+    ///             thread::sleep(Duration::from_millis(1));
+    ///         }
+    ///     });
+    ///     assert_eq!(control.clone().shutdown(timeout), Ok(()));
+    ///     assert_eq!(control.status(), Status::Interrupted);
+    ///
+    ///     let control = spawn(|_flag| {
+    ///         thread::sleep(Duration::from_secs(60));
+    ///     });
+    ///     let timeout = Duration::from_millis(10);
+    ///     assert_eq!(control.shutdown(timeout), Err(ShutdownError::Timeout));
+    /// }
+    /// ```
+    pub fn shutdown(self, timeout: Duration) -> Result<(), ShutdownError> {
+        self.stop();
+        if !self.wait_done(timeout) {
+            self.interrupt();
+            if !self.wait_done(timeout) {
+                return Err(ShutdownError::Timeout);
+            }
+        }
+        let _ = self.join_result();
+        Ok(())
+    }
+
+    fn wait_done(&self, timeout: Duration) -> bool {
+        self.completion_receiver().recv_timeout(timeout).is_ok()
+    }
+
+    /// Wait until thread reaches `target` status.
+    /// Waits forever if `timeout` is `None`. Blocks on the channel
+    /// of `completion_receiver()` without polling.
+    /// Returns `false` if the timeout expired or thread
+    /// reached another terminal status.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use std::thread;
+    /// use std::time::Duration;
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    ///     let (flag, control) = make_pair();
+    ///     assert_eq!(control.status(), Status::Running);
+    ///     let timeout = Some(Duration::from_millis(10));
+    ///     assert_eq!(control.wait_for_status(Status::Stopped, timeout), false);
+    ///     let handle = thread::spawn(move || {
+    ///         while flag.alive() {
+    ///         }
+    ///     });
+    ///     control.stop();
+    ///     assert_eq!(control.wait_for_status(Status::Stopped, None), true);
+    ///     assert_eq!(control.status().is_terminal(), true);
+    ///     handle.join().unwrap();
+    ///
+    ///     let (flag, control) = make_pair();
+    ///     drop(flag);
+    ///     assert_eq!(control.status(), Status::Finished);
+    ///     assert_eq!(control.wait_for_status(Status::Stopped, None), false);
+    /// }
+    /// ```
+    pub fn wait_for_status(&self, target: Status, timeout: Option<Duration>) -> bool {
+        if self.status() == target {
+            return true;
+        }
+        // Only the final status can follow, it's sent when the flag finishes
+        let receiver = self.completion_receiver();
+        let status = match timeout {
+            Some(timeout) => receiver.recv_timeout(timeout).ok(),
+            None => receiver.recv().ok(),
+        };
+        status == Some(target)
+    }
+
+    /// Return the time when the flag was dropped
+    /// or `None` if thread is still running.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use std::thread;
+    /// use std::time::SystemTime;
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    ///     let started = SystemTime::now();
+    ///     let (flag, control) = make_pair();
+    ///     assert_eq!(control.finished_at(), None);
+    ///     let handle = thread::spawn(move || {
+    ///         while flag.alive() {
+    ///         }
+    ///     });
+    ///     control.stop();
+    ///     handle.join().unwrap();
+    ///     let finished = control.finished_at().unwrap();
+    ///     assert!(finished >= started);
+    /// }
+    /// ```
+    pub fn finished_at(&self) -> Option<SystemTime> {
+        match self.shared.finished_at.load(Ordering::Acquire) {
+            0 => None,
+            nanos => Some(UNIX_EPOCH + Duration::from_nanos(nanos)),
+        }
+    }
+
+    /// Combines this control with `other`, so both threads are stopped
+    /// or interrupted at once and are done when both are done.
+    pub fn merge(self, other: Control) -> MergedControl {
+        MergedControl {
+            controls: vec![self, other],
+        }
+    }
+
+    /// Creates `WeakControl` which doesn't keep the shared state alive.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use thread_control::*;
+    ///
+    /// fn main() {
+    ///     let (flag, control) = make_pair();
+    ///     let weak = control.downgrade();
+    ///     assert!(weak.upgrade().is_some());
+    ///     drop(flag);
+    ///     assert_eq!(weak.upgrade().unwrap().is_done(), true);
+    ///     drop(control);
+    ///     assert!(weak.upgrade().is_none());
+    /// }
+    /// ```
+    pub fn downgrade(&self) -> WeakControl {
+        WeakControl {
+            shared: Arc::downgrade(&self.shared),
+            generation: self.generation,
+            created_at: self.created_at,
+            name: self.name.clone(),
+            ordering: self.ordering,
+        }
+    }
+}
+
+/// Control which holds only weak references to the shared state,
+/// made by `Control::downgrade()`. Caches of controls can keep it
+/// without pinning the state of finished threads.
+#[derive(Debug, Clone)]
+pub struct WeakControl {
+    shared: Weak<Shared>,
+    generation: u64,
+    created_at: &'static Location<'static>,
+    name: Option<Arc<str>>,
+    ordering: Ordering,
+}
+
+impl WeakControl {
+    /// Returns `Control` if any flag or control still exists.
+    /// It doesn't own the thread, so dropping it never triggers
+    /// `Control::interrupt_on_last_drop()`.
+    pub fn upgrade(&self) -> Option<Control> {
+        let shared = self.shared.upgrade()?;
+        Some(Control {
+            shared,
+            generation: self.generation,
+            created_at: self.created_at,
+            name: self.name.clone(),
+            ordering: self.ordering,
+            detached: false,
+            owning: false,
+        })
+    }
+}
+
+/// Control of several threads at once, made by `Control::merge()`.
+///
+/// Example:
+///
+/// ```rust
+/// use std::thread;
+/// use thread_control::*;
+///
+/// fn main() {
+///     let (first_flag, first) = make_pair();
+///     let (second_flag, second) = make_pair();
+///     let handles: Vec<_> = vec![first_flag, second_flag].into_iter().map(|flag| {
+///         thread::spawn(move || {
+///             while flag.alive() {
+///             }
+///         })
+///     }).collect();
+///     let merged = first.merge(second.clone());
+///     assert_eq!(merged.is_done(), false);
+///     merged.stop();
+///     for handle in handles {
+///         handle.join().unwrap();
+///     }
+///     assert_eq!(merged.is_done(), true);
+///     assert_eq!(merged.is_interrupted(), false);
+///     assert_eq!(second.status(), Status::Stopped);
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct MergedControl {
+    controls: Vec<Control>,
+}
+
+impl MergedControl {
+    /// Adds one more control.
+    pub fn merge(mut self, other: Control) -> MergedControl {
+        self.controls.push(other);
+        self
+    }
+
+    /// Interrupt execution of all threads.
+    pub fn interrupt(&self) {
+        for control in &self.controls {
+            control.interrupt();
+        }
+    }
+
+    /// Set stop flags of all threads.
+    pub fn stop(&self) {
+        for control in &self.controls {
+            control.stop();
+        }
+    }
+
+    /// Return `true` if all threads ended.
+    pub fn is_done(&self) -> bool {
+        self.controls.iter().all(Control::is_done)
+    }
+
+    /// Return `true` if any thread was interrupted or panicked.
+    pub fn is_interrupted(&self) -> bool {
+        self.controls.iter().any(Control::is_interrupted)
+    }
+}
+
+/// Blocking iterator over status transitions made by `Control::status_changes()`.
+#[derive(Debug)]
+pub struct StatusChanges {
+    first: Option<Status>,
+    completion: Option<mpsc::Receiver<Status>>,
+}
+
+impl Iterator for StatusChanges {
+    type Item = Status;
+
+    fn next(&mut self) -> Option<Status> {
+        if let Some(status) = self.first.take() {
+            if status.is_terminal() {
+                self.completion = None;
+            }
+            return Some(status);
+        }
+        self.completion.take()?.recv().ok()
+    }
+}
+
+/// Flag which re-reads the shared state only once per the number
+/// of checks, made by `Flag::cached()`.
+///
+/// The counter is kept by the owner thread (it's not `Sync`), so workers
+/// don't touch the shared cache line on every check. It delays
+/// observation of stop and interrupt by up to `checks` calls.
+///
+/// Example:
+///
+/// ```rust
+/// use thread_control::*;
+///
+/// fn main() {
+///     let (flag, control) = make_pair();
+///     let flag = flag.cached(10);
+///     assert_eq!(flag.alive(), true);
+///     control.stop();
+///     let mut late_checks = 0;
+///     while flag.alive() {
+///         late_checks += 1;
+///     }
+///     assert!(late_checks <= 10);
+/// }
+/// ```
+#[derive(Debug)]
+pub struct CachedFlag {
+    flag: Flag,
+    checks: u32,
+    remaining: Cell<u32>,
+}
+
+impl CachedFlag {
+    /// Check the flag isn't stopped or interrupted like `Flag::alive()`,
+    /// but uses the cached result while it lasts.
+    ///
+    /// # Panics
+    ///
+    /// This method panics, if interrupt flag was set.
+    pub fn alive(&self) -> bool {
+        let remaining = self.remaining.get();
+        if remaining > 0 {
+            self.remaining.set(remaining - 1);
+            return true;
+        }
+        let alive = self.flag.alive();
+        if alive {
+            self.remaining.set(self.checks);
+        }
+        alive
+    }
+
+    /// Returns the original flag.
+    pub fn into_inner(self) -> Flag {
+        self.flag
+    }
+}
+
+/// Flag which reads the shared state at most once per interval,
+/// made by `Flag::throttled_by_time()`.
+///
+/// The time of the last check is kept by the owner thread (it's not
+/// `Sync`). It delays observation of stop and interrupt by up to
+/// the interval.
+///
+/// Example:
+///
+/// ```rust
+/// use std::thread;
+/// use std::time::{Duration, Instant};
+/// use thread_control::*;
+///
+/// fn main() {
+/// #     // Timing assertions don't hold under the slow interpreter
+/// #     if cfg!(miri) { return; }
+///     let (flag, control) = make_pair();
+///     let interval = Duration::from_millis(10);
+///     let handle = thread::spawn(move || {
+///         let flag = flag.throttled_by_time(interval);
+///         while flag.alive() {
+///         }
+///         Instant::now()
+///     });
+///     thread::sleep(Duration::from_millis(50));
+///     let stopped = Instant::now();
+///     control.stop();
+///     let observed = handle.join().unwrap();
+///     assert!(observed.duration_since(stopped) < interval * 5);
+/// }
+/// ```
+#[derive(Debug)]
+pub struct TimedFlag {
+    flag: Flag,
+    interval: Duration,
+    checked: Cell<Option<Instant>>,
+}
+
+impl TimedFlag {
+    /// Check the flag isn't stopped or interrupted like `Flag::alive()`,
+    /// but uses the cached result until the interval elapses.
+    ///
+    /// # Panics
+    ///
+    /// This method panics, if interrupt flag was set.
+    pub fn alive(&self) -> bool {
+        if let Some(checked) = self.checked.get() {
+            if checked.elapsed() < self.interval {
+                return true;
+            }
+        }
+        let alive = self.flag.alive();
+        self.checked.set(if alive { Some(Instant::now()) } else { None });
+        alive
+    }
+
+    /// Returns the original flag.
+    pub fn into_inner(self) -> Flag {
+        self.flag
+    }
+}
+
+/// Reader which fails once its flag is stopped, made by `Flag::wrap_read()`.
+#[derive(Debug)]
+pub struct AliveRead<'a, R> {
+    flag: &'a Flag,
+    inner: R,
+}
+
+impl<'a, R> AliveRead<'a, R> {
+    /// Returns the wrapped reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<'a, R: io::Read> io::Read for AliveRead<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.flag.check_io()?;
+        self.inner.read(buf)
+    }
+}
+
+/// Writer which fails once its flag is stopped, made by `Flag::wrap_write()`.
+#[derive(Debug)]
+pub struct AliveWrite<'a, W> {
+    flag: &'a Flag,
+    inner: W,
+}
+
+impl<'a, W> AliveWrite<'a, W> {
+    /// Returns the wrapped writer.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<'a, W: io::Write> io::Write for AliveWrite<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.flag.check_io()?;
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.flag.check_io()?;
+        self.inner.flush()
+    }
+}
+
+/// Trait to run existing loop bodies under control of a `Flag`.
+///
+/// Example:
+///
+/// ```rust
+/// use std::ops::ControlFlow;
+/// use std::thread;
+/// use thread_control::*;
+///
+/// fn main() {
+///     let (flag, control) = make_pair();
+///     let handle = thread::spawn(move || {
+///         let mut counter = 0u64;
+///         let result = (|| {
+///             counter += 1;
+///             ControlFlow::<u64>::Continue(())
+///         }).run_controlled(&flag);
+///         (result, counter)
+///     });
+///     control.stop();
+///     let (result, _counter) = handle.join().unwrap();
+///     assert_eq!(result, None);
+///
+///     let (flag, _control) = make_pair();
+///     let mut counter = 0;
+///     let result = (|| {
+///         counter += 1;
+///         if counter == 10 {
+///             ControlFlow::Break(counter)
+///         } else {
+///             ControlFlow::Continue(())
+///         }
+///     }).run_controlled(&flag);
+///     assert_eq!(result, Some(10));
+/// }
+/// ```
+pub trait WithControl<R> {
+    /// Calls the body while the flag is alive.
+    /// Returns the value the body breaks with or `None` if stopped.
+    ///
+    /// # Panics
+    ///
+    /// This method panics, if interrupt flag was set.
+    fn run_controlled(&mut self, flag: &Flag) -> Option<R>;
+}
+
+impl<F, R> WithControl<R> for F
+    where F: FnMut() -> ControlFlow<R>
+{
+    fn run_controlled(&mut self, flag: &Flag) -> Option<R> {
+        while flag.alive() {
+            if let ControlFlow::Break(value) = self() {
+                return Some(value);
+            }
+        }
+        None
+    }
+}
+
+/// What `WorkerLoop` does when the body had no work.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backoff {
+    /// Call the body again immediately.
+    #[default]
+    Spin,
+    /// Yield to other threads.
+    Yield,
+    /// Sleep for the duration.
+    Sleep(Duration),
+}
+
+/// Builder of a worker loop which runs while the flag is alive.
+///
+/// Example:
+///
+/// ```rust
+/// use std::thread;
+/// use std::time::Duration;
+/// use thread_control::*;
+///
+/// fn run(work: bool) -> u32 {
+///     let (flag, control) = make_pair();
+///     let handle = thread::spawn(move || {
+///         let mut iterations = 0;
+///         WorkerLoop::new(flag)
+///             .backoff(Backoff::Sleep(Duration::from_millis(20)))
+///             .run(|| {
+///                 iterations += 1;
+///                 work
+///             });
+///         iterations
+///     });
+///     thread::sleep(Duration::from_millis(50));
+///     control.stop();
+///     handle.join().unwrap()
+/// }
+///
+/// fn main() {
+/// #     // Timing assertions don't hold under the slow interpreter
+/// #     if cfg!(miri) { return; }
+///     // Busy loop doesn't sleep
+///     assert!(run(true) > 100);
+///     // Idle loop sleeps after every iteration
+///     assert!(run(false) <= 5);
+/// }
+/// ```
+#[derive(Debug)]
+pub struct WorkerLoop {
+    flag: Flag,
+    backoff: Backoff,
+}
+
+impl WorkerLoop {
+    /// Creates loop controlled by `flag` which spins when idle.
+    pub fn new(flag: Flag) -> Self {
+        WorkerLoop {
+            flag,
+            backoff: Backoff::default(),
+        }
+    }
+
+    /// Sets what to do when the body had no work.
+    pub fn backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Calls the body while the flag is alive. The body returns
+    /// `true` if it did some work and `false` if it was idle.
+    ///
+    /// # Panics
+    ///
+    /// This method panics, if interrupt flag was set.
+    pub fn run<F>(self, mut body: F)
+        where F: FnMut() -> bool
+    {
+        while self.flag.alive() {
+            if body() {
+                continue;
+            }
+            match self.backoff {
+                Backoff::Spin => {
+                },
+                Backoff::Yield => {
+                    thread::yield_now();
+                },
+                Backoff::Sleep(dur) => {
+                    thread::sleep(dur);
+                },
+            }
+        }
+    }
+}
+
+/// Builder of a pair with optional features, made by `Control::builder()`.
+///
+/// Example:
+///
+/// ```rust
+/// use std::panic;
+/// use std::sync::atomic::Ordering;
+/// use std::sync::mpsc;
+/// use std::thread;
+/// use std::time::Duration;
+/// use thread_control::*;
+///
+/// fn main() {
+///     let (tx, rx) = mpsc::channel();
+///     let (flag, control) = Control::builder()
+///         .name("indexer")
+///         .ordering(Ordering::SeqCst)
+///         .on_stop(move |status| tx.send(status).unwrap())
+///         .watchdog(Duration::from_millis(50))
+///         .set_interrupt_on_panic(false)
+///         .build();
+///     assert_eq!(control.name(), Some("indexer"));
+///     let handle = thread::spawn(move || {
+///         // The watchdog stops the worker
+///         while flag.alive() {
+///         }
+///         panic!("unrelated failure");
+///     });
+///     assert!(handle.join().is_err());
+///     assert_eq!(rx.recv().unwrap(), Status::Stopped);
+///     assert_eq!(control.is_interrupted(), false);
+/// }
+/// ```
+pub struct ControlBuilder {
+    name: Option<String>,
+    ordering: Ordering,
+    on_stop: Vec<Callback>,
+    watchdog: Option<Duration>,
+    interrupt_on_panic: bool,
+    track_start: bool,
+}
+
+impl fmt::Debug for ControlBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ControlBuilder")
+            .field("name", &self.name)
+            .field("ordering", &self.ordering)
+            .field("on_stop", &self.on_stop.len())
+            .field("watchdog", &self.watchdog)
+            .field("interrupt_on_panic", &self.interrupt_on_panic)
+            .field("track_start", &self.track_start)
+            .finish()
+    }
+}
+
+impl ControlBuilder {
+    /// Sets the name returned by `Control::name()`.
+    pub fn name<S: Into<String>>(mut self, name: S) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Sets memory ordering of the stop flag. `Ordering::Relaxed` by default.
+    /// Stores use the release part of it and loads the acquire part.
+    pub fn ordering(mut self, ordering: Ordering) -> Self {
+        self.ordering = ordering;
+        self
+    }
+
+    /// Adds callback which is called with the final status
+    /// when the flag is done. It's called by the finishing thread.
+    pub fn on_stop<F>(mut self, f: F) -> Self
+        where F: FnOnce(Status) + Send + 'static
+    {
+        self.on_stop.push(Box::new(f));
+        self
+    }
+
+    /// Stops the thread gracefully after `dur` unless it's already done,
+    /// see `Control::stop_after()`.
+    pub fn watchdog(mut self, dur: Duration) -> Self {
+        self.watchdog = Some(dur);
+        self
+    }
+
+    /// Sets whether panics not raised by `Flag::alive()` mark the flag
+    /// interrupted, see `Flag::scope_panics_to_alive()`. `true` by default.
+    pub fn set_interrupt_on_panic(mut self, interrupt: bool) -> Self {
+        self.interrupt_on_panic = interrupt;
+        self
+    }
+
+    /// Makes the flag finish with `Status::NeverStarted`
+    /// if it's dropped before `Flag::mark_started()`.
+    pub fn track_start(mut self) -> Self {
+        self.track_start = true;
+        self
+    }
+
+    /// Makes pair with configured features.
+    #[track_caller]
+    pub fn build(self) -> (Flag, Control) {
+        let mut flag = Flag::new();
+        flag.name = self.name.map(Arc::from);
+        flag.ordering = self.ordering;
+        if !self.interrupt_on_panic {
+            flag.scope_panics_to_alive();
+        }
+        flag.shared.on_stop.0.lock().expect("callbacks poisoned").extend(self.on_stop);
+        flag.shared.track_start.store(self.track_start, Ordering::Relaxed);
+        let control = flag.take_control();
+        register_in_scope(&control);
+        #[cfg(feature = "registry")]
+        registry::register(&control);
+        if let Some(dur) = self.watchdog {
+            control.clone().stop_after(dur);
+        }
+        (flag, control)
+    }
+}
+
+/// Makes pair with connected flag and control.
+#[track_caller]
+pub fn make_pair() -> (Flag, Control) {
+    let flag = Flag::new();
+    let control = flag.take_control();
+    register_in_scope(&control);
+    (flag, control)
+}
+
+/// Connected flag and control as a named struct instead of a tuple.
+///
+/// Example:
+///
+/// ```rust
+/// use std::thread;
+/// use thread_control::*;
+///
+/// fn main() {
+///     let pair = make();
+///     let (flag, control) = pair.split();
+///     let Pair { flag, control } = Pair::from_pair((flag, control));
+///     let pair: Pair = (flag, control).into();
+///     let (flag, control): (Flag, Control) = pair.into();
+///     let handle = thread::spawn(move || {
+///         while flag.alive() {
+///         }
+///     });
+///     control.stop();
+///     handle.join().unwrap();
+///     assert_eq!(control.status(), Status::Stopped);
+/// }
+/// ```
+#[derive(Debug)]
+pub struct Pair {
+    /// Flag of the pair for the worker.
+    pub flag: Flag,
+    /// Control of the pair for the controller.
+    pub control: Control,
+}
+
+impl Pair {
+    /// Makes pair of the tuple form.
+    pub fn from_pair((flag, control): (Flag, Control)) -> Self {
+        Pair { flag, control }
+    }
+
+    /// Returns the tuple form of the pair.
+    pub fn split(self) -> (Flag, Control) {
+        (self.flag, self.control)
+    }
+}
+
+impl From<(Flag, Control)> for Pair {
+    fn from(pair: (Flag, Control)) -> Self {
+        Pair::from_pair(pair)
+    }
+}
+
+impl From<Pair> for (Flag, Control) {
+    fn from(pair: Pair) -> Self {
+        pair.split()
+    }
+}
+
+/// Makes pair like `make_pair()` as a `Pair`.
+#[track_caller]
+pub fn make() -> Pair {
+    Pair::from_pair(make_pair())
+}
+
+/// Makes pair like `make_pair()`, but the flag is treated as stopped
+/// once `ttl` elapsed since its creation, even without `stop()`.
+/// It's a safety cap against workers which run forever.
+///
+/// Example:
+///
+/// ```rust
+/// use std::thread;
+/// use std::time::{Duration, Instant};
+/// use thread_control::*;
+///
+/// fn main() {
+///     let started = Instant::now();
+///     let (flag, control) = make_pair_with_ttl(Duration::from_millis(50));
+///     let handle = thread::spawn(move || {
+///         while flag.alive() {
+///             thread::sleep(Duration::from_millis(1));
+///         }
+///     });
+///     handle.join().unwrap();
+///     assert!(started.elapsed() >= Duration::from_millis(50));
+///     assert_eq!(control.status(), Status::Stopped);
+/// }
+/// ```
+#[track_caller]
+pub fn make_pair_with_ttl(ttl: Duration) -> (Flag, Control) {
+    let mut flag = Flag::new();
+    flag.expires_at = Instant::now().checked_add(ttl);
+    let control = flag.take_control();
+    register_in_scope(&control);
+    (flag, control)
+}
+
+/// Spawns thread which runs `f` with a new flag and returns its control.
+/// The control keeps the handle of the thread, see `Control::join_result()`.
+/// If the thread can't be spawned, the status is `Status::NeverStarted`.
+#[track_caller]
+pub fn spawn<F>(f: F) -> Control
+    where F: FnOnce(Flag) + Send + 'static
+{
+    let (flag, control) = make_pair();
+    flag.shared.track_start.store(true, Ordering::Relaxed);
+    // The flag is dropped with the closure if spawning fails
+    let spawned = thread::Builder::new().spawn(move || {
+        flag.mark_started();
+        f(flag)
+    });
+    if let Ok(handle) = spawned {
+        *control.shared.handle.lock().expect("handle poisoned") = Some(handle);
+    }
+    control
+}
+
+/// Makes pair with a flag which is already stopped.
+///
+/// Example:
+///
+/// ```rust
+/// use thread_control::*;
+///
+/// fn main() {
+///     let (flag, control) = make_stopped_pair();
+///     assert_eq!(flag.alive(), false);
+///     assert_eq!(control.is_interrupted(), false);
+/// }
+/// ```
+#[track_caller]
+pub fn make_stopped_pair() -> (Flag, Control) {
+    let flag = Flag::new_stopped();
+    let control = flag.take_control();
+    register_in_scope(&control);
+    (flag, control)
+}
+
+/// Makes pair with a flag which is already interrupted.
+///
+/// Example:
+///
+/// ```rust
+/// use std::panic;
+/// use thread_control::*;
+///
+/// fn main() {
+///     let (flag, control) = make_interrupted_pair();
+///     assert_eq!(control.is_interrupted(), true);
+///     assert_eq!(flag.is_alive(), false);
+///     let result = panic::catch_unwind(move || {
+///         flag.alive();
+///     });
+///     assert!(result.is_err());
+/// }
+/// ```
+#[track_caller]
+pub fn make_interrupted_pair() -> (Flag, Control) {
+    let flag = Flag::new_interrupted();
+    let control = flag.take_control();
+    register_in_scope(&control);
+    (flag, control)
+}
+
+/// Makes pair with a flag which can be shared by multiple threads.
+///
+/// Since `Arc<Flag>` could be kept by any thread, it isn't dropped
+/// predictably and `Control::is_done()` can't rely on it. Call
+/// `Flag::finish()` when the work is completed to mark it done.
+///
+/// Example:
+///
+/// ```rust
+/// use std::thread;
+/// use thread_control::*;
+///
+/// fn main() {
+///     let (flag, control) = make_shared_pair();
+///     let handles: Vec<_> = (0..2).map(|_| {
+///         let flag = flag.clone();
+///         thread::spawn(move || {
+///             while flag.alive() {
+///             }
+///         })
+///     }).collect();
+///     control.stop();
+///     for handle in handles {
+///         handle.join().unwrap();
+///     }
+///     assert_eq!(control.is_done(), false);
+///     flag.finish();
+///     assert_eq!(control.is_done(), true);
+///     assert_eq!(control.status(), Status::Stopped);
+/// }
+/// ```
+#[track_caller]
+pub fn make_shared_pair() -> (Arc<Flag>, Control) {
+    let (flag, control) = make_pair();
+    (Arc::new(flag), control)
+}
+
+thread_local! {
+    // Controls of pairs made inside of `with_control_scope()`, innermost scope last
+    static SCOPES: RefCell<Vec<Vec<WeakControl>>> = const { RefCell::new(Vec::new()) };
+}
+
+fn register_in_scope(control: &Control) {
+    SCOPES.with(|scopes| {
+        if let Some(scope) = scopes.borrow_mut().last_mut() {
+            scope.push(control.downgrade());
+        }
+    });
+}
+
+// Stops controls of the scope even if it's left by panic
+struct ScopeGuard;
+
+impl Drop for ScopeGuard {
+    fn drop(&mut self) {
+        let scope = SCOPES.with(|scopes| scopes.borrow_mut().pop()).unwrap_or_default();
+        for control in scope.iter().filter_map(WeakControl::upgrade) {
+            control.stop();
+        }
+    }
+}
+
+/// Runs `f` and stops all pairs made on this thread while it runs
+/// by `make_pair()`, `FlagPool::make_pair()`, `spawn()` and the other
+/// constructors of pairs, when it returns or panics. It helps tests
+/// to not leak worker threads on early returns or failed assertions.
+///
+/// Example:
+///
+/// ```rust
+/// use std::thread;
+/// use thread_control::*;
+///
+/// fn main() {
+///     let (controls, handles): (Vec<_>, Vec<_>) = with_control_scope(|| {
+///         (0..3).map(|_| {
+///             let (flag, control) = make_pair();
+///             let handle = thread::spawn(move || {
+///                 while flag.alive() {
+///                 }
+///             });
+///             (control, handle)
+///         }).unzip()
+///     });
+///     for handle in handles {
+///         handle.join().unwrap();
+///     }
+///     assert!(controls.iter().all(|control| control.status() == Status::Stopped));
+///
+///     // Pairs of pools too
+///     let pool = FlagPool::new();
+///     let (control, handle) = with_control_scope(|| {
+///         let (flag, control) = pool.make_pair();
+///         let handle = thread::spawn(move || {
+///             while flag.alive() {
+///             }
+///         });
+///         (control, handle)
+///     });
+///     handle.join().unwrap();
+///     assert_eq!(control.status(), Status::Stopped);
+/// }
+/// ```
+pub fn with_control_scope<F, R>(f: F) -> R
+    where F: FnOnce() -> R
+{
+    SCOPES.with(|scopes| scopes.borrow_mut().push(Vec::new()));
+    let _guard = ScopeGuard;
+    f()
+}
+
+/// Flag of a pair made by `const_make_pair!()`, see `ConstControl`.
+#[derive(Debug, Clone, Copy)]
+pub struct ConstFlag {
+    alive: &'static AtomicBool,
+}
+
+impl ConstFlag {
+    /// Makes flag which watches `alive`, use `const_make_pair!()` instead.
+    pub const fn new(alive: &'static AtomicBool) -> Self {
+        ConstFlag { alive }
+    }
+
+    /// Check the flag isn't stopped.
+    /// Also returns `false` after `global_stop()`.
+    pub fn alive(&self) -> bool {
+        self.alive.load(Ordering::Relaxed) && !GLOBAL_STOP.load(Ordering::Relaxed)
+    }
+}
+
+/// Control of a pair made by `const_make_pair!()`, which can live in a `static`.
+///
+/// It's backed by a `static AtomicBool` instead of the shared state
+/// of `Control`, so `stop()` is a single atomic store and can be called
+/// from a signal handler. The price is that it only stops:
+///
+/// * There is no interrupt, status, completion or wakeup of parked
+///   or blocked workers; the worker sees the stop on its next check.
+/// * The stop is permanent, the pair can't be reset.
+/// * Every expansion of the macro has its own flag, so a macro
+///   expanded in a function gives the same pair on every call.
+///
+/// Example:
+///
+/// ```rust
+/// #[macro_use]
+/// extern crate thread_control;
+///
+/// use std::thread;
+/// use thread_control::*;
+///
+/// static WORKER: (ConstFlag, ConstControl) = const_make_pair!();
+///
+/// fn main() {
+///     let handle = thread::spawn(|| {
+///         let mut checks = 0u64;
+///         while WORKER.0.alive() {
+///             checks += 1;
+///         }
+///         checks
+///     });
+///     thread::spawn(|| WORKER.1.stop()).join().unwrap();
+///     handle.join().unwrap();
+///     assert_eq!(WORKER.1.is_stopped(), true);
+/// }
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct ConstControl {
+    alive: &'static AtomicBool,
+}
+
+impl ConstControl {
+    /// Makes control which stops `alive`, use `const_make_pair!()` instead.
+    pub const fn new(alive: &'static AtomicBool) -> Self {
+        ConstControl { alive }
+    }
+
+    /// Set stop flag. It's async-signal-safe.
+    pub fn stop(&self) {
+        self.alive.store(false, Ordering::Relaxed);
+    }
+
+    /// Return `true` if `stop()` was called.
+    pub fn is_stopped(&self) -> bool {
+        !self.alive.load(Ordering::Relaxed)
+    }
+}
+
+/// Makes `(ConstFlag, ConstControl)` in const context, e.g. for a `static`.
+#[macro_export]
+macro_rules! const_make_pair {
+    () => {{
+        static ALIVE: ::std::sync::atomic::AtomicBool = ::std::sync::atomic::AtomicBool::new(true);
+        ($crate::ConstFlag::new(&ALIVE), $crate::ConstControl::new(&ALIVE))
+    }};
+}
+
+/// Guard which stops controls registered with `Control::stop_on_process_exit()`
+/// when it's dropped, made by `process_exit_guard()`.
+///
+/// Rust doesn't stop other threads when `main()` returns, they are killed
+/// with the process in the middle of their work. Keep the guard in `main()`
+/// to stop them at its end. The registration is process-wide, every guard
+/// stops all registered controls.
+///
+/// Example:
+///
+/// ```rust
+/// use thread_control::*;
+///
+/// fn main() {
+///     let guard = process_exit_guard();
+///     let control = spawn(|flag| {
+///         while flag.alive() {
+///         }
+///     });
+///     control.clone().stop_on_process_exit();
+///     // The end of `main()`
+///     drop(guard);
+///     control.join_result().unwrap();
+///     assert_eq!(control.status(), Status::Stopped);
+/// }
+/// ```
+#[derive(Debug)]
+pub struct ProcessExitGuard {
+    _private: (),
+}
+
+impl Drop for ProcessExitGuard {
+    fn drop(&mut self) {
+        let controls: Vec<_> = EXIT_CONTROLS.lock().expect("exit controls poisoned").drain(..).collect();
+        for control in controls {
+            control.stop();
+        }
+    }
+}
+
+/// Makes guard which stops registered controls when dropped, see `ProcessExitGuard`.
+pub fn process_exit_guard() -> ProcessExitGuard {
+    ProcessExitGuard { _private: () }
+}
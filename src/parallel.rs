@@ -0,0 +1,65 @@
+//! Stopping of parallel pipelines fed by `ParallelBridge::par_bridge()`.
+//!
+//! Every rayon worker of a bridge pulls the next item under the lock
+//! of the bridge, so an iterator which checks the flag in `next()`
+//! ends the pipeline for all of them at once.
+//!
+//! Example:
+//!
+//! ```rust
+//! extern crate rayon;
+//! extern crate thread_control;
+//!
+//! use std::sync::atomic::{AtomicUsize, Ordering};
+//! use rayon::iter::{ParallelBridge, ParallelIterator};
+//! use thread_control::*;
+//!
+//! fn main() {
+//!     let (flag, control) = make_pair();
+//!     let handled = AtomicUsize::new(0);
+//!     // Endless source, only the stop ends it
+//!     flag.guard_parallel(0u64..).par_bridge().for_each(|_| {
+//!         if handled.fetch_add(1, Ordering::Relaxed) == 1000 {
+//!             control.stop();
+//!         }
+//!     });
+//!     assert!(handled.load(Ordering::Relaxed) > 1000);
+//!     assert_eq!(flag.is_alive(), false);
+//! }
+//! ```
+
+use Flag;
+
+/// Iterator which ends when the flag is stopped or interrupted,
+/// made by `Flag::guard_parallel()`.
+#[derive(Debug)]
+pub struct GuardedIter<'a, T> {
+    flag: &'a Flag,
+    iter: T,
+    ended: bool,
+}
+
+impl<'a, T: Iterator> Iterator for GuardedIter<'a, T> {
+    type Item = T::Item;
+
+    fn next(&mut self) -> Option<T::Item> {
+        // Doesn't panic on interrupt, it would poison the lock of the bridge
+        if self.ended || !self.flag.is_alive() {
+            self.ended = true;
+            return None;
+        }
+        self.iter.next()
+    }
+}
+
+impl Flag {
+    /// Wrap `iter` to yield items while the flag is alive,
+    /// use it as the source of `par_bridge()`.
+    pub fn guard_parallel<T: Iterator>(&self, iter: T) -> GuardedIter<'_, T> {
+        GuardedIter {
+            flag: self,
+            iter,
+            ended: false,
+        }
+    }
+}
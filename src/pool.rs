@@ -0,0 +1,175 @@
+//! Recycling of flags and controls.
+//!
+//! Every pair allocates its shared state. `FlagPool` keeps the state
+//! of pairs it made and reuses it for new pairs once both the flag and
+//! all its controls are dropped, so systems which run many short-lived
+//! tasks don't allocate it per task.
+//!
+//! Example:
+//!
+//! ```rust
+//! use std::thread;
+//! use thread_control::*;
+//!
+//! fn main() {
+//!     let pool = FlagPool::new();
+//!     for _ in 0..3 {
+//!         let (flag, control) = pool.make_pair();
+//!         assert_eq!(flag.is_alive(), true);
+//!         assert_eq!(control.status(), Status::Running);
+//!         let handle = thread::spawn(move || {
+//!             while flag.alive() {
+//!             }
+//!         });
+//!         control.stop();
+//!         handle.join().unwrap();
+//!         assert_eq!(control.status(), Status::Stopped);
+//!     }
+//!     assert_eq!(pool.len(), 1);
+//! }
+//! ```
+
+use std::fmt;
+use std::panic::Location;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{fence, AtomicBool, Ordering};
+use {register_in_scope, Control, Flag, Shared, Status, GENERATION};
+#[cfg(feature = "tokio")]
+use tokio::sync::watch;
+
+// The state of a pair kept by the pool
+struct Pooled {
+    shared: Arc<Shared>,
+    own_interrupt: Arc<AtomicBool>,
+}
+
+impl Pooled {
+    // Nobody else references the state, neither strong nor weak
+    fn is_free(&self) -> bool {
+        Arc::strong_count(&self.shared) == 1 && Arc::weak_count(&self.shared) == 0
+    }
+
+    fn reset(&self) {
+        let shared = &self.shared;
+        shared.alive.store(true, Ordering::Relaxed);
+        (*self.own_interrupt).store(false, Ordering::Relaxed);
+        shared.clones.store(1, Ordering::Relaxed);
+        shared.interrupt.store(false, Ordering::Relaxed);
+        {
+            let mut interrupts = shared.interrupts.lock().expect("interrupts poisoned");
+            interrupts.clear();
+            interrupts.push(self.own_interrupt.clone());
+        }
+        shared.status.store(Status::Running.to_u8(), Ordering::Relaxed);
+        shared.finished_at.store(0, Ordering::Relaxed);
+        shared.paused.store(false, Ordering::Relaxed);
+        shared.parked.store(false, Ordering::Relaxed);
+        *shared.worker.lock().expect("worker poisoned") = None;
+        *shared.reason.lock().expect("reason poisoned") = None;
+        *shared.message.lock().expect("message poisoned") = None;
+        shared.budget.store(i64::MAX, Ordering::Relaxed);
+        shared.completions.lock().expect("completions poisoned").clear();
+        *shared.handle.lock().expect("handle poisoned") = None;
+        shared.acked.store(false, Ordering::Relaxed);
+        shared.taken.store(false, Ordering::Relaxed);
+        shared.on_stop.0.lock().expect("callbacks poisoned").clear();
+        shared.interrupt_on_last_drop.store(false, Ordering::Relaxed);
+        shared.work.store(0, Ordering::Relaxed);
+        if let Some(log) = shared.work_log.get() {
+            for record in log.iter() {
+                record.at.store(0, Ordering::Relaxed);
+            }
+        }
+        shared.work_next.store(0, Ordering::Relaxed);
+        shared.checkpoint.store(0, Ordering::Relaxed);
+        shared.started.store(false, Ordering::Relaxed);
+        shared.track_start.store(false, Ordering::Relaxed);
+        #[cfg(feature = "tokio")]
+        {
+            *shared.status_watch.lock().expect("status watch poisoned") = watch::Sender::new(Status::Running);
+        }
+        // A fired descriptor would wake the next worker at once
+        #[cfg(all(target_os = "linux", feature = "linux"))]
+        {
+            *shared.eventfd.lock().expect("eventfd poisoned") = None;
+        }
+    }
+
+    fn make_pair(&self, created_at: &'static Location<'static>) -> (Flag, Control) {
+        let flag = Flag {
+            shared: self.shared.clone(),
+            own_interrupt: self.own_interrupt.clone(),
+            generation: GENERATION.fetch_add(1, Ordering::Relaxed),
+            created_at,
+            name: None,
+            ordering: Ordering::Relaxed,
+            watched: Vec::new(),
+            scoped_panics: false,
+            raised: AtomicBool::new(false),
+            expires_at: None,
+            ancestors: Vec::new(),
+        };
+        let control = flag.take_control();
+        (flag, control)
+    }
+}
+
+/// Pool which reuses the shared state of dropped pairs.
+pub struct FlagPool {
+    pooled: Mutex<Vec<Pooled>>,
+}
+
+impl fmt::Debug for FlagPool {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FlagPool").field("len", &self.len()).finish()
+    }
+}
+
+impl Default for FlagPool {
+    fn default() -> Self {
+        FlagPool::new()
+    }
+}
+
+impl FlagPool {
+    /// Creates new empty pool.
+    pub fn new() -> Self {
+        FlagPool {
+            pooled: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Makes pair like `make_pair()`, but reuses the state of a pair
+    /// made by this pool if its flag and controls are all dropped.
+    #[track_caller]
+    pub fn make_pair(&self) -> (Flag, Control) {
+        let created_at = Location::caller();
+        let mut pooled = self.pooled.lock().expect("pool poisoned");
+        if let Some(free) = pooled.iter().find(|pooled| pooled.is_free()) {
+            // Synchronizes with the last drop like `Arc` does
+            fence(Ordering::Acquire);
+            free.reset();
+            let (flag, control) = free.make_pair(created_at);
+            register_in_scope(&control);
+            return (flag, control);
+        }
+        let flag = Flag::new();
+        let control = flag.take_control();
+        pooled.push(Pooled {
+            shared: flag.shared.clone(),
+            own_interrupt: flag.own_interrupt.clone(),
+        });
+        register_in_scope(&control);
+        (flag, control)
+    }
+
+    /// Returns the number of states kept by the pool, used or free.
+    pub fn len(&self) -> usize {
+        self.pooled.lock().expect("pool poisoned").len()
+    }
+
+    /// Returns `true` if the pool keeps no states.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
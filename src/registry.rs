@@ -0,0 +1,114 @@
+//! Global registry of named pairs for ops tooling.
+//!
+//! Pairs made by `ControlBuilder` with a name are registered
+//! on creation and removed when their flag finishes.
+//!
+//! Example:
+//!
+//! ```rust
+//! use std::thread;
+//! use thread_control::*;
+//! use thread_control::registry;
+//!
+//! fn main() {
+//!     let mut handles = Vec::new();
+//!     for name in &["registry-ingest", "registry-export"] {
+//!         let (flag, control) = Control::builder().name(*name).build();
+//!         let handle = thread::spawn(move || {
+//!             while flag.alive() {
+//!                 thread::yield_now();
+//!             }
+//!         });
+//!         handles.push((handle, control));
+//!     }
+//!     let names: Vec<_> = registry::list().into_iter()
+//!         .filter(|&(ref name, _)| name.starts_with("registry-"))
+//!         .collect();
+//!     assert_eq!(names, vec![
+//!         ("registry-export".to_owned(), Status::Running),
+//!         ("registry-ingest".to_owned(), Status::Running),
+//!     ]);
+//!     registry::find("registry-ingest").unwrap().stop();
+//!     let (handle, _control) = handles.remove(0);
+//!     handle.join().unwrap();
+//!     assert!(registry::find("registry-ingest").is_none());
+//!     assert!(registry::find("registry-export").is_some());
+//!     for (handle, control) in handles {
+//!         control.stop();
+//!         handle.join().unwrap();
+//!     }
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use {Control, Status, WeakControl};
+
+// Keyed by the generation too, because names aren't unique
+type Entries = HashMap<(String, u64), WeakControl>;
+
+static ENTRIES: OnceLock<Mutex<Entries>> = OnceLock::new();
+
+fn entries() -> MutexGuard<'static, Entries> {
+    ENTRIES.get_or_init(Default::default).lock().expect("registry poisoned")
+}
+
+pub(crate) fn register(control: &Control) {
+    if let Some(name) = control.name() {
+        entries().insert((name.to_owned(), control.generation), control.downgrade());
+    }
+}
+
+pub(crate) fn deregister(name: &str, generation: u64) {
+    entries().remove(&(name.to_owned(), generation));
+}
+
+/// Returns names and statuses of registered pairs ordered by name.
+/// Listing doesn't take over the pairs, a detached worker keeps running.
+///
+/// Example:
+///
+/// ```rust
+/// use std::thread;
+/// use thread_control::*;
+/// use thread_control::registry;
+///
+/// fn main() {
+///     let (flag, control) = Control::builder().name("registry-detached").build();
+///     control.interrupt_on_last_drop();
+///     let handle = thread::spawn(move || {
+///         while flag.alive() {
+///             thread::yield_now();
+///         }
+///     });
+///     control.detach();
+///     let listed: Vec<_> = registry::list().into_iter()
+///         .filter(|&(ref name, _)| name == "registry-detached")
+///         .collect();
+///     assert_eq!(listed, vec![("registry-detached".to_owned(), Status::Running)]);
+///     // Neither listing nor a found control interrupt the worker on drop
+///     drop(registry::find("registry-detached").unwrap());
+///     let control = registry::find("registry-detached").unwrap();
+///     assert_eq!(control.is_interrupted(), false);
+///     assert_eq!(control.status(), Status::Running);
+///     control.stop();
+///     assert!(handle.join().is_ok());
+/// }
+/// ```
+pub fn list() -> Vec<(String, Status)> {
+    let mut list: Vec<_> = entries().iter()
+        .filter_map(|((name, generation), weak)| {
+            weak.upgrade().map(|control| (name.clone(), *generation, control.status()))
+        })
+        .collect();
+    list.sort_by(|a, b| (&a.0, a.1).cmp(&(&b.0, b.1)));
+    list.into_iter().map(|(name, _, status)| (name, status)).collect()
+}
+
+/// Returns control of the oldest registered pair with `name`.
+pub fn find(name: &str) -> Option<Control> {
+    entries().iter()
+        .filter(|((entry, _), _)| entry == name)
+        .min_by_key(|((_, generation), _)| *generation)
+        .and_then(|(_, weak)| weak.upgrade())
+}
@@ -0,0 +1,133 @@
+//! Ordered shutdown of workers which depend on each other.
+//!
+//! A worker which uses another one should stop first, otherwise it
+//! works with a stopped dependency for a while. `ShutdownPlan` keeps
+//! controls with dependencies between them and stops dependents first,
+//! a control is stopped once everything which depends on it is done.
+//!
+//! Example:
+//!
+//! ```rust
+//! use std::sync::{Arc, Mutex};
+//! use std::thread;
+//! use std::time::{Duration, Instant};
+//! use thread_control::*;
+//!
+//! fn main() {
+//!     let stopped_at = Arc::new(Mutex::new(Vec::new()));
+//!     let worker = |name: &'static str| {
+//!         let stopped_at = stopped_at.clone();
+//!         spawn(move |flag| {
+//!             while flag.alive() {
+//!                 thread::sleep(Duration::from_millis(1));
+//!             }
+//!             stopped_at.lock().unwrap().push((name, Instant::now()));
+//!         })
+//!     };
+//!     let mut plan = ShutdownPlan::new();
+//!     // Added out of order, the order comes from the dependencies
+//!     let c = plan.add(worker("c"));
+//!     let a = plan.add(worker("a"));
+//!     let b = plan.add(worker("b"));
+//!     plan.depends_on(a, b);
+//!     plan.depends_on(b, c);
+//!     assert_eq!(plan.stop(Duration::from_secs(30)), Ok(()));
+//!     let mut stopped_at = stopped_at.lock().unwrap().clone();
+//!     stopped_at.sort_by_key(|&(_, at)| at);
+//!     let order: Vec<_> = stopped_at.iter().map(|&(name, _)| name).collect();
+//!     assert_eq!(order, vec!["a", "b", "c"]);
+//!     assert!(stopped_at[0].1 < stopped_at[1].1 && stopped_at[1].1 < stopped_at[2].1);
+//! }
+//! ```
+
+use std::mem;
+use std::time::Duration;
+use {Control, ShutdownError};
+
+/// Controls stopped in the order of their dependencies.
+#[derive(Debug, Default)]
+pub struct ShutdownPlan {
+    controls: Vec<Control>,
+    // Pairs of (dependent, dependency) indices
+    edges: Vec<(usize, usize)>,
+}
+
+impl ShutdownPlan {
+    /// Creates empty plan.
+    pub fn new() -> Self {
+        ShutdownPlan::default()
+    }
+
+    /// Adds `control` and returns its index for `depends_on()`.
+    pub fn add(&mut self, control: Control) -> usize {
+        self.controls.push(control);
+        self.controls.len() - 1
+    }
+
+    /// Declares that the worker of `dependent` uses the worker of
+    /// `dependency`, so `dependency` is stopped only after `dependent`
+    /// is done.
+    ///
+    /// # Panics
+    ///
+    /// This method panics, if an index isn't returned by `add()`
+    /// or the dependency makes a cycle.
+    pub fn depends_on(&mut self, dependent: usize, dependency: usize) {
+        let len = self.controls.len();
+        assert!(dependent < len && dependency < len, "control isn't in the shutdown plan");
+        assert!(!self.reaches(dependency, dependent), "shutdown plan can't have cycles");
+        self.edges.push((dependent, dependency));
+    }
+
+    // Whether `to` is `from` or one of its dependencies
+    fn reaches(&self, from: usize, to: usize) -> bool {
+        let mut seen = vec![false; self.controls.len()];
+        let mut stack = vec![from];
+        while let Some(node) = stack.pop() {
+            if node == to {
+                return true;
+            }
+            if mem::replace(&mut seen[node], true) {
+                continue;
+            }
+            stack.extend(self.edges.iter()
+                .filter(|&&(dependent, _)| dependent == node)
+                .map(|&(_, dependency)| dependency));
+        }
+        false
+    }
+
+    /// Stops the controls in stages, dependents first. Every stage stops
+    /// all controls whose dependents are done and waits up to `timeout`
+    /// for each of them to be done. If it expires, the rest isn't stopped,
+    /// because their dependents still use them.
+    pub fn stop(&self, timeout: Duration) -> Result<(), ShutdownError> {
+        let mut dependents = vec![0; self.controls.len()];
+        for &(_, dependency) in &self.edges {
+            dependents[dependency] += 1;
+        }
+        let mut stopped = vec![false; self.controls.len()];
+        loop {
+            let stage: Vec<_> = (0..self.controls.len())
+                .filter(|&index| !stopped[index] && dependents[index] == 0)
+                .collect();
+            if stage.is_empty() {
+                return Ok(());
+            }
+            for &index in &stage {
+                self.controls[index].stop();
+                stopped[index] = true;
+            }
+            for &index in &stage {
+                if !self.controls[index].wait_done(timeout) {
+                    return Err(ShutdownError::Timeout);
+                }
+            }
+            for &(dependent, dependency) in &self.edges {
+                if stage.contains(&dependent) {
+                    dependents[dependency] -= 1;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,100 @@
+//! Interrupts of threads blocked in system calls.
+//!
+//! A thread blocked in a system call like `read()` doesn't check
+//! its flag until the call returns. `Control::interrupt_blocking()`
+//! sends `SIGUSR1` to the thread registered with
+//! `Flag::register_thread_for_signals()`, so the call fails with
+//! `EINTR` (`ErrorKind::Interrupted`) and the thread observes
+//! the interrupt on the next check.
+//!
+//! Limitations:
+//!
+//! * It's available on unix platforms only.
+//! * The handler of `SIGUSR1` is replaced by an empty one for the whole
+//!   process without `SA_RESTART`, don't use the signal for other purposes.
+//! * Calls which are restarted by the library (e.g. `read_exact()`)
+//!   or ignore `EINTR` still block.
+//! * The flag must be dropped by the registered thread, the registration
+//!   is cleared when the flag finishes.
+//!
+//! Example:
+//!
+//! ```rust
+//! use std::io::Read;
+//! use std::os::unix::net::UnixStream;
+//! use std::thread;
+//! use std::time::Duration;
+//! use thread_control::*;
+//!
+//! fn main() {
+//!     let (mut reader, _writer) = UnixStream::pair().unwrap();
+//!     let (flag, control) = make_pair();
+//!     let handle = thread::spawn(move || {
+//!         flag.register_thread_for_signals();
+//!         let mut buf = [0; 16];
+//!         while flag.alive() {
+//!             // Blocks forever, nobody writes
+//!             let _ = reader.read(&mut buf);
+//!         }
+//!     });
+//!     // The signal is lost if it comes between checks, so repeat it
+//!     while !control.is_done() {
+//!         control.interrupt_blocking();
+//!         thread::sleep(Duration::from_millis(10));
+//!     }
+//!     assert!(handle.join().is_err());
+//!     assert_eq!(control.status(), Status::Interrupted);
+//! }
+//! ```
+
+use std::mem;
+use std::ptr;
+use std::sync::{Mutex, Once};
+use libc;
+use {Control, Flag};
+
+static HANDLER: Once = Once::new();
+
+extern "C" fn wake_up(_: libc::c_int) {
+}
+
+// Without `SA_RESTART` blocked calls fail with `EINTR`
+fn install_handler() {
+    HANDLER.call_once(|| unsafe {
+        let mut action: libc::sigaction = mem::zeroed();
+        action.sa_sigaction = wake_up as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        libc::sigemptyset(&mut action.sa_mask);
+        libc::sigaction(libc::SIGUSR1, &action, ptr::null_mut());
+    });
+}
+
+// The thread can exit after the flag is finished, so it must not be signaled later
+pub(crate) fn forget(thread: &Mutex<Option<libc::pthread_t>>) {
+    *thread.lock().expect("signal thread poisoned") = None;
+}
+
+impl Flag {
+    /// Register the current thread to be woken up from blocking
+    /// system calls by `Control::interrupt_blocking()`.
+    /// Only the last registered thread is kept.
+    pub fn register_thread_for_signals(&self) {
+        install_handler();
+        let current = unsafe { libc::pthread_self() };
+        *self.shared.signal_thread.lock().expect("signal thread poisoned") = Some(current);
+    }
+}
+
+impl Control {
+    /// Interrupt execution of thread like `interrupt()` and send
+    /// `SIGUSR1` to the registered thread to break a blocking call.
+    pub fn interrupt_blocking(&self) {
+        self.interrupt();
+        // The lock keeps the registration from being cleared meanwhile
+        let thread = self.shared.signal_thread.lock().expect("signal thread poisoned");
+        if let Some(thread) = *thread {
+            unsafe {
+                libc::pthread_kill(thread, libc::SIGUSR1);
+            }
+        }
+    }
+}
@@ -0,0 +1,113 @@
+//! Utilities for deterministic tests of workers.
+//!
+//! Example:
+//!
+//! ```rust
+//! use std::sync::Arc;
+//! use std::sync::atomic::{AtomicUsize, Ordering};
+//! use std::thread;
+//! use thread_control::test_support::StepFlag;
+//!
+//! fn main() {
+//!     let (flag, control) = StepFlag::new();
+//!     let worker = flag.clone();
+//!     let iterations = Arc::new(AtomicUsize::new(0));
+//!     let worker_iterations = iterations.clone();
+//!     let handle = thread::spawn(move || {
+//!         while worker.alive() {
+//!             worker_iterations.fetch_add(1, Ordering::Relaxed);
+//!         }
+//!     });
+//!     for _ in 0..3 {
+//!         flag.step();
+//!     }
+//!     // Between the 3rd and the 4th iteration
+//!     control.interrupt();
+//!     flag.step();
+//!     assert!(handle.join().is_err());
+//!     assert_eq!(iterations.load(Ordering::Relaxed), 3);
+//!     assert_eq!(control.interrupt_acknowledged(), true);
+//! }
+//! ```
+
+use std::sync::{Arc, Condvar, Mutex};
+use {register_in_scope, Control, Flag};
+
+#[derive(Debug, Default)]
+struct Steps {
+    granted: u64,
+    taken: u64,
+}
+
+#[derive(Debug)]
+struct Inner {
+    flag: Flag,
+    steps: Mutex<Steps>,
+    changed: Condvar,
+}
+
+/// Flag which checks are single-stepped by the test.
+///
+/// `alive()` of the worker blocks until the test calls `step()`,
+/// and `step()` returns when the check is done. So the test can
+/// change the state exactly between iterations without sleeps.
+#[derive(Debug, Clone)]
+pub struct StepFlag {
+    inner: Arc<Inner>,
+}
+
+// Marks the check taken even if `alive()` panics, so `step()` returns
+struct Taken<'a> {
+    inner: &'a Inner,
+}
+
+impl<'a> Drop for Taken<'a> {
+    fn drop(&mut self) {
+        self.inner.steps.lock().expect("steps poisoned").taken += 1;
+        self.inner.changed.notify_all();
+    }
+}
+
+impl StepFlag {
+    /// Makes pair with connected step flag and control.
+    #[track_caller]
+    pub fn new() -> (StepFlag, Control) {
+        let flag = Flag::new();
+        let control = flag.take_control();
+        register_in_scope(&control);
+        let inner = Inner {
+            flag,
+            steps: Mutex::new(Steps::default()),
+            changed: Condvar::new(),
+        };
+        (StepFlag { inner: Arc::new(inner) }, control)
+    }
+
+    /// Waits for the next `step()` and checks the flag like `Flag::alive()`.
+    ///
+    /// # Panics
+    ///
+    /// This method panics, if interrupt flag was set.
+    pub fn alive(&self) -> bool {
+        {
+            let mut steps = self.inner.steps.lock().expect("steps poisoned");
+            while steps.granted == steps.taken {
+                steps = self.inner.changed.wait(steps).expect("steps poisoned");
+            }
+        }
+        let _taken = Taken { inner: &self.inner };
+        self.inner.flag.alive()
+    }
+
+    /// Lets the worker do one check and waits until it's done.
+    /// Blocks forever if the worker doesn't check the flag anymore.
+    pub fn step(&self) {
+        let mut steps = self.inner.steps.lock().expect("steps poisoned");
+        steps.granted += 1;
+        let granted = steps.granted;
+        self.inner.changed.notify_all();
+        while steps.taken < granted {
+            steps = self.inner.changed.wait(steps).expect("steps poisoned");
+        }
+    }
+}